@@ -55,7 +55,7 @@ $(document).ready(function(){
     if (search_by !== null) {
         $('.dropdown-item.search-' + search_by).addClass('active').addClass('bg-accent');
     } else {
-        $('.dropdown-item.search-name').addClass('active').addClass('bg-accent');
+        $('.dropdown-item.search-all').addClass('active').addClass('bg-accent');
     }
     console.log("yay + " + search_by);
 })
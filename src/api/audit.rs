@@ -0,0 +1,58 @@
+use std::sync::Arc;
+
+use rocket::State;
+
+use crate::api::auth::AuthToken;
+use crate::api::error::ApiError;
+use crate::api::page::Page;
+use crate::audit::{AuditEntry, AuditLog};
+use crate::param::DateTimeParam;
+
+/// The number of entries returned when no `limit` is given, as the log is never rotated
+static DEFAULT_LIMIT: usize = 100;
+
+#[get("/api/audit?<category>&<name>&<version>&<since>&<until>&<offset>&<limit>")]
+pub fn audit(
+    audit_log: State<Arc<AuditLog>>,
+    token: AuthToken,
+    category: Option<String>,
+    name: Option<String>,
+    version: Option<String>,
+    since: Option<DateTimeParam>,
+    until: Option<DateTimeParam>,
+    offset: Option<usize>,
+    limit: Option<usize>,
+) -> Result<Page<AuditEntry>, ApiError> {
+    if !token.is_admin() {
+        return Err(ApiError::forbidden(
+            "only admin tokens can browse the audit log",
+        ));
+    }
+
+    let offset = offset.unwrap_or(0);
+    let limit = limit.unwrap_or(DEFAULT_LIMIT);
+    let (entries, more) = audit_log
+        .entries(
+            |entry| {
+                let is_after = since
+                    .as_ref()
+                    .map_or(true, |since| entry.date() >= since.value());
+                let is_before = until
+                    .as_ref()
+                    .map_or(true, |until| entry.date() <= until.value());
+
+                is_after
+                    && is_before
+                    && entry.is_about(
+                        category.as_ref().map(String::as_str),
+                        name.as_ref().map(String::as_str),
+                        version.as_ref().map(String::as_str),
+                    )
+            },
+            offset,
+            limit,
+        )
+        .map_err(|e| ApiError::internal(&e))?;
+
+    Ok(Page::partial(entries, offset, Some(limit), more))
+}
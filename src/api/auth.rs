@@ -1,13 +1,206 @@
+use std::fmt::{self, Display, Formatter};
+use std::io::{self, BufRead};
+use std::iter;
 use std::sync::Arc;
 
+use data_encoding::HEXLOWER;
+use failure::{format_err, Error};
+use libnest::package::CategoryName;
+use rand::distributions::Alphanumeric;
+use rand::{thread_rng, Rng};
 use rocket::http::Status;
 use rocket::request::{self, FromRequest, Request};
 use rocket::Outcome;
 use rocket::State;
+use serde::de::{self, Deserialize, Deserializer};
+use serde::ser::{Serialize, Serializer};
+use sha2::{Digest, Sha256};
+use subtle::ConstantTimeEq;
 
-use crate::config::Config;
+use crate::audit::{AuditEntry, AuditLog, AuditOutcome, RequestOrigin};
+use crate::config::{Config, Scope, Token};
 
-pub struct AuthToken(String);
+/// The digest of an authentication token, as written in the configuration.
+///
+/// The supported formats are `sha256$<salt>$<hex digest>` (the digest being the SHA-256 of the salt followed by the token),
+/// and the standard encodings of argon2 (`$argon2i$...`, `$argon2id$...`) and bcrypt (`$2b$...`).
+#[derive(Clone, Eq, PartialEq, Hash, Debug)]
+pub enum TokenHash {
+    Sha256 { salt: String, digest: Vec<u8> },
+    Argon2(String),
+    Bcrypt(String),
+}
+
+impl TokenHash {
+    pub fn parse(s: &str) -> Result<TokenHash, Error> {
+        if s.starts_with("$argon2") {
+            Ok(TokenHash::Argon2(s.to_string()))
+        } else if s.starts_with("$2a$") || s.starts_with("$2b$") || s.starts_with("$2y$") {
+            Ok(TokenHash::Bcrypt(s.to_string()))
+        } else {
+            let parts = s.split('$').collect::<Vec<_>>();
+
+            match parts.as_slice() {
+                ["sha256", salt, digest] if !salt.is_empty() => Ok(TokenHash::Sha256 {
+                    salt: salt.to_string(),
+                    digest: HEXLOWER.decode(digest.to_lowercase().as_bytes())?,
+                }),
+                _ => Err(format_err!("{}: unsupported token hash format", s)),
+            }
+        }
+    }
+
+    /// Hash the given token with SHA-256 and a random salt
+    pub fn sha256(token: &str) -> TokenHash {
+        let salt = gen_salt();
+        let digest = salted_sha256(&salt, token);
+
+        TokenHash::Sha256 { salt, digest }
+    }
+
+    /// Hash the given token with argon2 (default parameters) and a random salt
+    pub fn argon2(token: &str) -> Result<TokenHash, Error> {
+        let encoded = argon2::hash_encoded(
+            token.as_bytes(),
+            gen_salt().as_bytes(),
+            &argon2::Config::default(),
+        )?;
+
+        Ok(TokenHash::Argon2(encoded))
+    }
+
+    /// Hash the given token with bcrypt (default cost)
+    pub fn bcrypt(token: &str) -> Result<TokenHash, Error> {
+        Ok(TokenHash::Bcrypt(bcrypt::hash(
+            token,
+            bcrypt::DEFAULT_COST,
+        )?))
+    }
+
+    /// Test if the given token matches this digest.
+    ///
+    /// The comparison is done in constant time to avoid leaking any information about the expected digest.
+    pub fn verify(&self, token: &str) -> bool {
+        match self {
+            TokenHash::Sha256 { salt, digest } => {
+                salted_sha256(salt, token).ct_eq(digest.as_slice()).into()
+            }
+            TokenHash::Argon2(encoded) => {
+                argon2::verify_encoded(encoded, token.as_bytes()).unwrap_or(false)
+            }
+            TokenHash::Bcrypt(encoded) => bcrypt::verify(token, encoded).unwrap_or(false),
+        }
+    }
+}
+
+impl Display for TokenHash {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            TokenHash::Sha256 { salt, digest } => {
+                write!(f, "sha256${}${}", salt, HEXLOWER.encode(digest))
+            }
+            TokenHash::Argon2(encoded) | TokenHash::Bcrypt(encoded) => write!(f, "{}", encoded),
+        }
+    }
+}
+
+impl Serialize for TokenHash {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.to_string())
+    }
+}
+
+impl<'de> Deserialize<'de> for TokenHash {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<TokenHash, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        TokenHash::parse(&s).map_err(de::Error::custom)
+    }
+}
+
+fn gen_salt() -> String {
+    let mut rng = thread_rng();
+    iter::repeat(())
+        .map(|()| rng.sample(Alphanumeric))
+        .take(16)
+        .collect()
+}
+
+fn salted_sha256(salt: &str, token: &str) -> Vec<u8> {
+    let mut sha256 = Sha256::default();
+    sha256.input(salt.as_bytes());
+    sha256.input(token.as_bytes());
+    sha256.result().to_vec()
+}
+
+/// Implementation of `nest-server hash-token [--sha256|--argon2|--bcrypt] [<token>]`.
+///
+/// If the token isn't given as an argument, it is read from the standard input.
+/// Returns the digest to paste in the `hash` field of a token in the configuration.
+pub fn hash_token_command(args: &[String]) -> Result<String, Error> {
+    let mut algorithm = "--sha256";
+    let mut token = None;
+
+    for arg in args {
+        match arg.as_str() {
+            "--sha256" | "--argon2" | "--bcrypt" => algorithm = arg.as_str(),
+            _ if arg.starts_with("--") => Err(format_err!("{}: unknown option", arg))?,
+            _ => token = Some(arg.clone()),
+        }
+    }
+
+    let token = match token {
+        Some(token) => token,
+        None => {
+            let mut line = String::new();
+            io::stdin().lock().read_line(&mut line)?;
+            line.trim_end_matches(|c| c == '\n' || c == '\r')
+                .to_string()
+        }
+    };
+
+    if token.is_empty() {
+        Err(format_err!("the token to hash is empty"))?;
+    }
+
+    let hash = match algorithm {
+        "--argon2" => TokenHash::argon2(&token)?,
+        "--bcrypt" => TokenHash::bcrypt(&token)?,
+        _ => TokenHash::sha256(&token),
+    };
+
+    Ok(hash.to_string())
+}
+
+/// The identity behind the `X-Auth-Token` header of an authenticated request.
+pub struct AuthToken(Token);
+
+impl AuthToken {
+    /// The name of the token that authenticated the request
+    pub fn identity(&self) -> &str {
+        self.0.name()
+    }
+
+    /// Test if the token was granted the `admin` scope
+    pub fn is_admin(&self) -> bool {
+        self.0.has_scope(Scope::Admin)
+    }
+
+    /// Test if the token is allowed to act with the given scope on packages of at least one category.
+    ///
+    /// It can be checked before the category of the package is known.
+    pub fn is_allowed_anywhere(&self, scope: Scope) -> bool {
+        self.0.has_scope(scope)
+            && self
+                .0
+                .categories()
+                .map_or(true, |categories| !categories.is_empty())
+    }
+
+    /// Test if the token is allowed to act with the given scope on a package of the given category
+    pub fn is_allowed(&self, scope: Scope, category: &CategoryName) -> bool {
+        self.0.has_scope(scope) && self.0.can_access(category)
+    }
+}
 
 impl<'a, 'r> FromRequest<'a, 'r> for AuthToken {
     type Error = ();
@@ -16,20 +209,44 @@ impl<'a, 'r> FromRequest<'a, 'r> for AuthToken {
         let config = request
             .guard::<State<Arc<Config>>>()
             .expect("can't retrieve the config state in auth handler");
+        let audit_log = request
+            .guard::<State<Arc<AuditLog>>>()
+            .expect("can't retrieve the audit log state in auth handler");
+
+        let failure = |details: &str| {
+            audit_log.record(
+                AuditEntry::new(&RequestOrigin::from(request), AuditOutcome::Failure)
+                    .details(details),
+            );
+            Outcome::Failure((Status::Forbidden, ()))
+        };
 
         let auth_tokens: Vec<_> = request.headers().get("X-Auth-Token").collect();
 
         if auth_tokens.len() != 1 {
-            return Outcome::Failure((Status::Forbidden, ()));
+            return failure("missing or duplicated authentication token");
         }
 
         let token = auth_tokens[0]; // OK
 
-        // That's not exactly high-level cryptography, but honestly, who cares about security anyway ¯\_(ツ)_/¯.
-        if config.auth_token() == token {
-            Outcome::Success(AuthToken(token.to_string()))
+        // The header is `<name>.<secret>`, so that only the digest of the named token is verified,
+        // as argon2 and bcrypt digests are slow to verify on purpose.
+        // Without a dot, the whole header is the secret of the `default` token (the legacy `auth_token`).
+        let (candidate, secret) = match token.find('.') {
+            Some(i) => match config.token_named(&token[..i]) {
+                Some(candidate) => (candidate, &token[i + 1..]),
+                None => return failure("unknown authentication token name"),
+            },
+            None => match config.token_named("default") {
+                Some(candidate) => (candidate, token),
+                None => return failure("invalid authentication token"),
+            },
+        };
+
+        if candidate.hash().verify(secret) {
+            Outcome::Success(AuthToken(candidate.clone()))
         } else {
-            Outcome::Failure((Status::Forbidden, ()))
+            failure("invalid authentication token")
         }
     }
 }
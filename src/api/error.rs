@@ -0,0 +1,119 @@
+use failure::Error;
+use rocket::http::Status;
+use rocket::response::{self, Responder};
+use rocket::{Request, Response};
+use rocket_contrib::json::Json;
+use serde_json::{json, Value};
+
+use crate::package::limits::LimitExceededError;
+use crate::package::PackageNotFoundError;
+
+/// An error returned by the API.
+///
+/// It is sent as a JSON object with a machine-readable `code`, a human-readable `message`
+/// and optional `details` whose content depends on the code.
+#[derive(Clone, PartialEq, Debug)]
+pub struct ApiError {
+    status: Status,
+    code: String,
+    message: String,
+    details: Option<Value>,
+}
+
+impl ApiError {
+    pub fn new<S: Into<String>>(status: Status, code: &str, message: S) -> Self {
+        Self {
+            status,
+            code: code.to_string(),
+            message: message.into(),
+            details: None,
+        }
+    }
+
+    /// Build an error out of the given status only, using its reason as a code and message
+    pub fn from_status(status: Status) -> Self {
+        ApiError::new(
+            status,
+            &status.reason.to_lowercase().replace(' ', "_"),
+            status.reason,
+        )
+    }
+
+    /// Build an error out of a [`failure::Error`], exposing the chain of its causes in the details
+    pub fn from_failure(status: Status, code: &str, error: &Error) -> Self {
+        let causes = error
+            .iter_chain()
+            .map(|cause| cause.to_string())
+            .collect::<Vec<_>>();
+
+        ApiError::new(status, code, error.to_string()).details(json!({ "causes": causes }))
+    }
+
+    pub fn forbidden<S: Into<String>>(message: S) -> Self {
+        ApiError::new(Status::Forbidden, "forbidden", message)
+    }
+
+    /// Build an error out of a failure of the cache layer, distinguishing missing packages from other errors
+    pub fn from_cache(error: &Error) -> Self {
+        if error.downcast_ref::<PackageNotFoundError>().is_some() {
+            ApiError::from_failure(Status::NotFound, "package_not_found", error)
+        } else {
+            ApiError::from_failure(Status::InternalServerError, "cache_error", error)
+        }
+    }
+
+    /// Build an error out of a failure to explore an NPF, distinguishing NPFs exceeding the limits from invalid ones
+    pub fn from_npf(error: &Error) -> Self {
+        if let Some(e) = error.downcast_ref::<LimitExceededError>() {
+            ApiError::payload_too_large(e)
+        } else {
+            ApiError::from_failure(Status::BadRequest, "invalid_npf", error)
+        }
+    }
+
+    pub fn payload_too_large(error: &LimitExceededError) -> Self {
+        ApiError::new(Status::PayloadTooLarge, "limit_exceeded", error.to_string())
+            .details(json!({ "limit": error.limit(), "max": error.max() }))
+    }
+
+    pub fn package_not_found() -> Self {
+        ApiError::new(
+            Status::NotFound,
+            "package_not_found",
+            "the package doesn't exist",
+        )
+    }
+
+    pub fn internal(error: &Error) -> Self {
+        ApiError::from_failure(Status::InternalServerError, "internal_error", error)
+    }
+
+    pub fn details(mut self, details: Value) -> Self {
+        self.details = Some(details);
+        self
+    }
+
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+}
+
+impl<'r> Responder<'r> for ApiError {
+    fn respond_to(self, req: &Request) -> response::Result<'r> {
+        let body = json!({
+            "code": self.code,
+            "message": self.message,
+            "details": self.details,
+        });
+
+        Response::build_from(Json(body).respond_to(req)?)
+            .status(self.status)
+            .ok()
+    }
+}
+
+/// Test if the given request targets the API, and should therefore receive JSON errors
+pub fn is_api_request(request: &Request) -> bool {
+    let path = request.uri().path();
+    path == "/api" || path.starts_with("/api/")
+}
@@ -0,0 +1,52 @@
+use std::sync::{Arc, RwLock};
+
+use rocket::http::ContentType;
+use rocket::response::Content;
+use rocket::State;
+use rocket_contrib::json::Json;
+
+use crate::config::Config;
+use crate::package::graph::Graph;
+use crate::package::NPFManager;
+use crate::param::GraphFormatParam;
+
+/// The format in which a dependency graph is sent
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug)]
+pub enum GraphFormat {
+    /// A JSON list of nodes, edges and cycles
+    Json,
+    /// The Graphviz DOT language
+    Dot,
+}
+
+/// A dependency graph, rendered in the requested format
+#[derive(Responder)]
+pub enum GraphResponse {
+    Json(Json<Graph>),
+    Dot(Content<String>),
+}
+
+impl GraphResponse {
+    pub fn new(graph: Graph, format: Option<GraphFormatParam>, name: &str) -> Self {
+        match format.map(Into::into).unwrap_or(GraphFormat::Json) {
+            GraphFormat::Json => GraphResponse::Json(Json(graph)),
+            GraphFormat::Dot => GraphResponse::Dot(Content(
+                ContentType::new("text", "vnd.graphviz"),
+                graph.to_dot(name),
+            )),
+        }
+    }
+}
+
+#[get("/api/graph?<format>")]
+pub fn graph(
+    config: State<Arc<Config>>,
+    npf_manager: State<Arc<RwLock<NPFManager>>>,
+    format: Option<GraphFormatParam>,
+) -> GraphResponse {
+    let npf_manager = npf_manager
+        .read()
+        .expect("can't open the NPF manager in read-only mode");
+
+    GraphResponse::new(npf_manager.graph(), format, config.name().as_ref())
+}
@@ -1,6 +1,13 @@
+pub mod audit;
 pub mod auth;
+pub mod error;
+pub mod graph;
 pub mod home;
 pub mod package;
+pub mod page;
 pub mod pull;
+pub mod report;
+pub mod resolve;
 pub mod search;
+pub mod signing;
 pub mod upload;
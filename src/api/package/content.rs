@@ -2,8 +2,10 @@ use std::sync::{Arc, RwLock};
 
 use libnest::package::PackageID;
 use rocket::State;
+use rocket_contrib::json;
 use rocket_contrib::json::JsonValue;
 
+use crate::api::error::ApiError;
 use crate::config::Config;
 use crate::package::NPFManager;
 use crate::param::{CategoryNameParam, PackageNameParam, VersionParam};
@@ -15,7 +17,7 @@ pub fn content(
     category: CategoryNameParam,
     name: PackageNameParam,
     version: VersionParam,
-) -> Option<JsonValue> {
+) -> Result<JsonValue, ApiError> {
     let npf_manager = npf_manager
         .read()
         .expect("can't open the NPF manager in read-only mode");
@@ -27,9 +29,9 @@ pub fn content(
         version.clone().into(),
     );
 
-    if let Ok(files) = npf_manager.content_of(&id) {
-        serde_json::to_value(files).ok().map(Into::into)
-    } else {
-        None
-    }
+    let files = npf_manager
+        .content_of(&id)
+        .map_err(|e| ApiError::from_cache(&e))?;
+
+    Ok(json!(files))
 }
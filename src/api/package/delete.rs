@@ -1,39 +1,124 @@
 use std::fs;
+use std::io;
 use std::path::PathBuf;
-use std::sync::Arc;
+use std::sync::{Arc, RwLock};
 
+use libnest::package::PackageID;
 use rocket::http::Status;
 use rocket::State;
 
 use crate::api::auth::AuthToken;
-use crate::config::Config;
+use crate::api::error::ApiError;
+use crate::audit::{AuditEntry, AuditLog, AuditOutcome, RequestOrigin};
+use crate::config::{Config, Scope};
+use crate::package::signature;
+use crate::package::NPFManager;
 use crate::param::{CategoryNameParam, PackageNameParam, VersionParam};
 
-#[delete("/api/p/<category>/<name>/<version>")]
+/// Delete a version of a package, refusing to break the packages depending on it unless forced.
+///
+/// The NPF manager is locked from the dependents check to the removal of the files, but uploads don't take that lock:
+/// a dependent uploaded meanwhile isn't known until the cache is updated, and may still be broken by the deletion.
+#[delete("/api/p/<category>/<name>/<version>?<force>")]
 pub fn delete(
     config: State<Arc<Config>>,
-    _token: AuthToken,
+    npf_manager: State<Arc<RwLock<NPFManager>>>,
+    audit_log: State<Arc<AuditLog>>,
+    origin: RequestOrigin,
+    token: AuthToken,
     category: CategoryNameParam,
     name: PackageNameParam,
     version: VersionParam,
-) -> Status {
-    let path = PathBuf::from(config.package_dir())
-        .join(category.value().as_ref())
-        .join(name.value().as_ref())
-        .join(format!(
-            "{}-{}.nest",
-            name.value().as_ref(),
-            version.value()
-        ));
-
-    // Remove the NPF.
-    // The file system notifier will finish the job and update the cache
-    if path.exists() && path.is_file() {
-        match fs::remove_file(&path) {
-            Ok(_) => Status::NoContent,
-            Err(_) => Status::InternalServerError,
+    force: Option<bool>,
+) -> Result<Status, ApiError> {
+    let id = PackageID::from(
+        config.name().clone(),
+        category.clone().into(),
+        name.clone().into(),
+        version.clone().into(),
+    );
+    let mut note = None;
+
+    let r: Result<(), ApiError> = try {
+        // Ensure the token is allowed to delete packages in this category
+        if !token.is_allowed(Scope::Delete, category.value()) {
+            Err(ApiError::forbidden(
+                "the token isn't allowed to delete in this category",
+            ))?;
+        }
+
+        let path = PathBuf::from(config.package_dir())
+            .join(category.value().as_ref())
+            .join(name.value().as_ref())
+            .join(format!(
+                "{}-{}.nest",
+                name.value().as_ref(),
+                version.value()
+            ));
+
+        if !path.exists() || !path.is_file() {
+            Err(ApiError::package_not_found())?;
+        }
+
+        // Refuse to break packages depending on this one, unless an admin forces the deletion.
+        // The lock is held until the files are removed, so that the cache doesn't change in the meantime
+        let npf_manager = npf_manager
+            .read()
+            .expect("can't open the NPF manager in read-only mode");
+        let dependents = npf_manager
+            .dependents_broken_by_removal_of(&id)
+            .into_iter()
+            .cloned()
+            .collect::<Vec<_>>();
+
+        if !dependents.is_empty() {
+            if !force.unwrap_or_default() {
+                Err(ApiError::new(
+                    Status::Conflict,
+                    "breaking_delete",
+                    format!(
+                        "{} package(s) depend on this version and would be broken by its deletion",
+                        dependents.len()
+                    ),
+                )
+                .details(serde_json::json!({ "dependents": dependents })))?;
+            } else if !token.is_admin() {
+                Err(ApiError::forbidden(
+                    "only admin tokens can delete a package other packages depend on",
+                ))?;
+            }
+            note = Some("forced deletion of a package other packages depend on");
+        }
+
+        // Remove the NPF and then its signature, if any, so that the NPF is never left without it.
+        // The file system notifier will finish the job and update the cache
+        let sig_path = signature::signature_path(&path);
+        let removed: Result<(), io::Error> = try {
+            fs::remove_file(&path)?;
+            if sig_path.exists() {
+                fs::remove_file(&sig_path)?;
+            }
+        };
+        removed.map_err(|e| {
+            ApiError::from_failure(Status::InternalServerError, "delete_failed", &e.into())
+        })?;
+    };
+
+    let entry = AuditEntry::new(&origin, AuditOutcome::Success)
+        .identity(token.identity())
+        .package(&id);
+
+    match r {
+        Ok(_) => {
+            audit_log.record(match note {
+                Some(note) => entry.details(note),
+                None => entry,
+            });
+            Ok(Status::NoContent)
+        }
+        Err(e) => {
+            audit_log.record(entry.outcome(AuditOutcome::Failure).details(e.message()));
+            Err(e)
         }
-    } else {
-        Status::NotFound
     }
 }
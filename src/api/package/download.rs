@@ -8,6 +8,7 @@ use rocket::State;
 use rocket::{response, Request, Response};
 use semver::Version;
 
+use crate::api::error::ApiError;
 use crate::config::Config;
 use crate::param::{CategoryNameParam, PackageNameParam, VersionParam};
 
@@ -38,7 +39,7 @@ pub fn download(
     category: CategoryNameParam,
     name: PackageNameParam,
     version: VersionParam,
-) -> Option<DownloadFile> {
+) -> Result<DownloadFile, ApiError> {
     let path = PathBuf::from(config.package_dir())
         .join(category.value().as_ref())
         .join(name.value().as_ref())
@@ -48,9 +49,11 @@ pub fn download(
             version.value()
         ));
 
-    NamedFile::open(&path).ok().map(|file| DownloadFile {
-        file,
-        name: name.into(),
-        version: version.into(),
-    })
+    NamedFile::open(&path)
+        .map(|file| DownloadFile {
+            file,
+            name: name.into(),
+            version: version.into(),
+        })
+        .map_err(|_| ApiError::package_not_found())
 }
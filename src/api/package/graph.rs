@@ -0,0 +1,42 @@
+use std::sync::{Arc, RwLock};
+
+use libnest::package::PackageID;
+use rocket::State;
+
+use crate::api::error::ApiError;
+use crate::api::graph::GraphResponse;
+use crate::config::Config;
+use crate::package::graph::Direction;
+use crate::package::NPFManager;
+use crate::param::{
+    CategoryNameParam, DirectionParam, GraphFormatParam, PackageNameParam, VersionParam,
+};
+
+#[get("/api/p/<category>/<name>/<version>/graph?<format>&<depth>&<direction>")]
+pub fn graph(
+    config: State<Arc<Config>>,
+    npf_manager: State<Arc<RwLock<NPFManager>>>,
+    category: CategoryNameParam,
+    name: PackageNameParam,
+    version: VersionParam,
+    format: Option<GraphFormatParam>,
+    depth: Option<usize>,
+    direction: Option<DirectionParam>,
+) -> Result<GraphResponse, ApiError> {
+    let npf_manager = npf_manager
+        .read()
+        .expect("can't open the NPF manager in read-only mode");
+
+    let id = PackageID::from(
+        config.name().clone(),
+        category.into(),
+        name.into(),
+        version.into(),
+    );
+    let direction = direction.map(Into::into).unwrap_or(Direction::Dependencies);
+
+    npf_manager
+        .graph_of(&id, direction, depth)
+        .map(|graph| GraphResponse::new(graph, format, &id.to_string()))
+        .ok_or_else(ApiError::package_not_found)
+}
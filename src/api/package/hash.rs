@@ -1,41 +1,38 @@
-use std::fs::File;
-use std::path::PathBuf;
-use std::sync::Arc;
+use std::sync::{Arc, RwLock};
 
-use data_encoding::HEXUPPER;
+use libnest::package::PackageID;
 use rocket::State;
 use rocket_contrib::json::JsonValue;
 use serde_json::json;
-use sha2::{Digest, Sha256};
 
+use crate::api::error::ApiError;
 use crate::config::Config;
+use crate::package::NPFManager;
 use crate::param::{CategoryNameParam, PackageNameParam, VersionParam};
 
 #[get("/api/p/<category>/<name>/<version>/hash")]
 pub fn hash(
     config: State<Arc<Config>>,
+    npf_manager: State<Arc<RwLock<NPFManager>>>,
     category: CategoryNameParam,
     name: PackageNameParam,
     version: VersionParam,
-) -> Option<JsonValue> {
-    let path = PathBuf::from(config.package_dir())
-        .join(category.value().as_ref())
-        .join(name.value().as_ref())
-        .join(format!(
-            "{}-{}.nest",
-            name.value().as_ref(),
-            version.value()
-        ));
+) -> Result<JsonValue, ApiError> {
+    let npf_manager = npf_manager
+        .read()
+        .expect("can't open the NPF manager in read-only mode");
 
-    File::open(path)
-        .and_then(|mut file| {
-            let mut sha256 = Sha256::default();
-            std::io::copy(&mut file, &mut sha256).map(|_| {
-                let hash = sha256.result();
-                JsonValue(json!({
-                    "sha256": HEXUPPER.encode(hash.as_ref())
-                }))
-            })
-        })
-        .ok()
+    let id = PackageID::from(
+        config.name().clone(),
+        category.into(),
+        name.into(),
+        version.into(),
+    );
+
+    // The digest is computed once, when the package is added to the cache
+    let sha256 = npf_manager
+        .sha256_of(&id)
+        .map_err(|e| ApiError::from_cache(&e))?;
+
+    Ok(JsonValue(json!({ "sha256": sha256 })))
 }
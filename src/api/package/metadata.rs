@@ -4,6 +4,7 @@ use libnest::package::PackageShortName;
 use rocket::State;
 use rocket_contrib::json::JsonValue;
 
+use crate::api::error::ApiError;
 use crate::package::NPFManager;
 use crate::param::{CategoryNameParam, PackageNameParam};
 
@@ -12,16 +13,15 @@ pub fn metadata(
     npf_manager: State<Arc<RwLock<NPFManager>>>,
     category: CategoryNameParam,
     name: PackageNameParam,
-) -> Option<JsonValue> {
+) -> Result<JsonValue, ApiError> {
     let npf_manager = npf_manager
         .read()
         .expect("can't open the NPF manager in read-only mode");
 
     let short_name = PackageShortName::from(category.into(), name.into());
 
-    if let Some(manifest) = npf_manager.manifest_of(&short_name) {
-        serde_json::to_value(manifest).ok().map(Into::into)
-    } else {
-        None
-    }
+    npf_manager
+        .manifest_of(&short_name)
+        .map(|manifest| JsonValue(npf_manager.index_entry_of(manifest)))
+        .ok_or_else(ApiError::package_not_found)
 }
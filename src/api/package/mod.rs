@@ -1,6 +1,10 @@
 pub mod content;
 pub mod delete;
 pub mod download;
+pub mod graph;
 pub mod hash;
 pub mod metadata;
+pub mod rdeps;
+pub mod safety;
+pub mod signer;
 pub mod version;
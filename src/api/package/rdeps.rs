@@ -0,0 +1,42 @@
+use std::sync::{Arc, RwLock};
+
+use libnest::package::PackageShortName;
+use rocket::State;
+use rocket_contrib::json::Json;
+
+use crate::api::error::ApiError;
+use crate::package::rdeps::ReverseDependency;
+use crate::package::NPFManager;
+use crate::param::{CategoryNameParam, PackageNameParam, VersionParam};
+
+// Ranked after `/api/p/<category>/<name>/<version>`, which forwards as `rdeps` isn't a valid version
+#[get("/api/p/<category>/<name>/rdeps?<version>", rank = 1)]
+pub fn rdeps(
+    npf_manager: State<Arc<RwLock<NPFManager>>>,
+    category: CategoryNameParam,
+    name: PackageNameParam,
+    version: Option<VersionParam>,
+) -> Result<Json<Vec<ReverseDependency>>, ApiError> {
+    let npf_manager = npf_manager
+        .read()
+        .expect("can't open the NPF manager in read-only mode");
+
+    let short_name = PackageShortName::from(category.into(), name.into());
+
+    if npf_manager.manifest_of(&short_name).is_none() {
+        return Err(ApiError::package_not_found());
+    }
+
+    let rdeps = npf_manager
+        .reverse_dependencies_of(&short_name)
+        .into_iter()
+        .filter(|rdep| {
+            version
+                .as_ref()
+                .map_or(true, |version| rdep.matches(version.value()))
+        })
+        .cloned()
+        .collect();
+
+    Ok(Json(rdeps))
+}
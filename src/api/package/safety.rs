@@ -0,0 +1,37 @@
+use std::sync::{Arc, RwLock};
+
+use libnest::package::PackageID;
+use rocket::State;
+use rocket_contrib::json::Json;
+
+use crate::api::error::ApiError;
+use crate::config::Config;
+use crate::package::safety::SafetyFinding;
+use crate::package::NPFManager;
+use crate::param::{CategoryNameParam, PackageNameParam, VersionParam};
+
+#[get("/api/p/<category>/<name>/<version>/safety")]
+pub fn safety(
+    config: State<Arc<Config>>,
+    npf_manager: State<Arc<RwLock<NPFManager>>>,
+    category: CategoryNameParam,
+    name: PackageNameParam,
+    version: VersionParam,
+) -> Result<Json<Vec<SafetyFinding>>, ApiError> {
+    let npf_manager = npf_manager
+        .read()
+        .expect("can't open the NPF manager in read-only mode");
+
+    let id = PackageID::from(
+        config.name().clone(),
+        category.into(),
+        name.into(),
+        version.into(),
+    );
+
+    let findings = npf_manager
+        .safety_of(&id)
+        .map_err(|e| ApiError::from_cache(&e))?;
+
+    Ok(Json(findings))
+}
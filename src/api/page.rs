@@ -0,0 +1,240 @@
+use std::cmp::Reverse;
+
+use chrono::{DateTime, Utc};
+use libnest::package::{PackageManifest, PackageShortName};
+use rocket::response::{self, Responder};
+use rocket::{Request, Response};
+use rocket_contrib::json::Json;
+use serde::Serialize;
+
+use crate::package::{ContentSearchResult, NPFManager};
+
+/// The order in which results are returned
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug)]
+pub enum SortKey {
+    /// By name, then category
+    Name,
+    /// By category, then name
+    Category,
+    /// Most recently updated first
+    Date,
+    /// Best matches first
+    Relevance,
+}
+
+/// A slice of a result set, as requested with the `offset` and `limit` query parameters.
+///
+/// It is sent as a JSON array, and the total number of results is given in the `X-Total-Count` header.
+/// If the result set is incomplete, the `X-Truncated` header is set.
+#[derive(Clone, Debug)]
+pub struct Page<T> {
+    items: Vec<T>,
+    total: usize,
+    offset: usize,
+    limit: Option<usize>,
+    truncated: bool,
+}
+
+impl<T> Page<T> {
+    pub fn paginate(items: Vec<T>, offset: Option<usize>, limit: Option<usize>) -> Self {
+        let total = items.len();
+        let offset = offset.unwrap_or(0);
+        let items = items
+            .into_iter()
+            .skip(offset)
+            .take(limit.unwrap_or(usize::max_value()))
+            .collect();
+
+        Self {
+            items,
+            total,
+            offset,
+            limit,
+            truncated: false,
+        }
+    }
+
+    /// Build a page from a result set that was only read up to the end of the page.
+    ///
+    /// Its size isn't known, so the total counts the results up to the end of the page, plus one if `more` follow.
+    /// In that case, the page is also marked as truncated.
+    pub fn partial(items: Vec<T>, offset: usize, limit: Option<usize>, more: bool) -> Self {
+        Self {
+            total: offset + items.len() + more as usize,
+            items,
+            offset,
+            limit,
+            truncated: more,
+        }
+    }
+
+    /// Mark the result set as incomplete
+    pub fn truncated(mut self, truncated: bool) -> Self {
+        self.truncated = truncated;
+        self
+    }
+
+    /// Transform the items of the page, keeping its position in the result set
+    pub fn map<U, F: FnMut(T) -> U>(self, f: F) -> Page<U> {
+        Page {
+            items: self.items.into_iter().map(f).collect(),
+            total: self.total,
+            offset: self.offset,
+            limit: self.limit,
+            truncated: self.truncated,
+        }
+    }
+
+    /// Transform the items of the page, failing if any of them can't be transformed
+    pub fn try_map<U, E, F: FnMut(T) -> Result<U, E>>(self, f: F) -> Result<Page<U>, E> {
+        Ok(Page {
+            items: self.items.into_iter().map(f).collect::<Result<_, _>>()?,
+            total: self.total,
+            offset: self.offset,
+            limit: self.limit,
+            truncated: self.truncated,
+        })
+    }
+
+    pub fn items(&self) -> &[T] {
+        &self.items
+    }
+
+    pub fn total(&self) -> usize {
+        self.total
+    }
+
+    pub fn offset(&self) -> usize {
+        self.offset
+    }
+
+    pub fn is_truncated(&self) -> bool {
+        self.truncated
+    }
+
+    /// The offset of the previous page, if any
+    pub fn prev_offset(&self) -> Option<usize> {
+        match self.limit {
+            Some(limit) if self.offset > 0 => Some(self.offset.saturating_sub(limit)),
+            _ => None,
+        }
+    }
+
+    /// The offset of the next page, if any
+    pub fn next_offset(&self) -> Option<usize> {
+        Some(self.offset + self.items.len()).filter(|next| *next < self.total)
+    }
+}
+
+impl<'r, T: Serialize> Responder<'r> for Page<T> {
+    fn respond_to(self, req: &Request) -> response::Result<'r> {
+        let mut response = Response::build_from(Json(self.items).respond_to(req)?);
+        response
+            .raw_header("X-Total-Count", self.total.to_string())
+            .raw_header("X-Offset", self.offset.to_string());
+        if self.truncated {
+            response.raw_header("X-Truncated", "true");
+        }
+        response.ok()
+    }
+}
+
+/// The date of the most recent version of a package
+pub fn last_update(manifest: &PackageManifest) -> Option<&DateTime<Utc>> {
+    manifest
+        .versions()
+        .values()
+        .map(|version| version.wrap_date())
+        .max()
+}
+
+/// How well a name matches a query: exact matches first, then prefixes, then anything else
+fn name_relevance(name: &str, query: &str) -> u8 {
+    if name == query {
+        0
+    } else if name.starts_with(query) {
+        1
+    } else if name.contains(query) {
+        2
+    } else {
+        3
+    }
+}
+
+pub fn sort_manifests(manifests: &mut Vec<&PackageManifest>, key: SortKey, query: &str) {
+    match key {
+        SortKey::Relevance => manifests.sort_by_key(|manifest| {
+            (
+                name_relevance(manifest.name().as_ref(), query),
+                manifest.name().clone(),
+            )
+        }),
+        _ => sort_by_manifest(manifests, key, |manifest| manifest),
+    }
+}
+
+/// Sort items holding a manifest by name, category or date.
+///
+/// Sorting by relevance depends on the kind of items, so it leaves them untouched.
+pub fn sort_by_manifest<T, F>(items: &mut Vec<T>, key: SortKey, manifest_of: F)
+where
+    F: Fn(&T) -> &PackageManifest,
+{
+    match key {
+        SortKey::Name => items.sort_by(|a, b| {
+            let (a, b) = (manifest_of(a), manifest_of(b));
+            (a.name(), a.category()).cmp(&(b.name(), b.category()))
+        }),
+        SortKey::Category => items.sort_by(|a, b| {
+            let (a, b) = (manifest_of(a), manifest_of(b));
+            (a.category(), a.name()).cmp(&(b.category(), b.name()))
+        }),
+        SortKey::Date => items.sort_by_key(|item| Reverse(last_update(manifest_of(item)).cloned())),
+        SortKey::Relevance => (),
+    }
+}
+
+pub fn sort_content_results(
+    results: &mut Vec<ContentSearchResult>,
+    key: SortKey,
+    query: &str,
+    npf_manager: &NPFManager,
+) {
+    match key {
+        SortKey::Name => {
+            results.sort_by(|a, b| (a.name().name(), a.path()).cmp(&(b.name().name(), b.path())))
+        }
+        SortKey::Category => results.sort_by(|a, b| {
+            (a.name().category(), a.name().name(), a.path()).cmp(&(
+                b.name().category(),
+                b.name().name(),
+                b.path(),
+            ))
+        }),
+        SortKey::Date => results.sort_by_key(|result| {
+            let short_name = PackageShortName::from(
+                result.name().category().clone(),
+                result.name().name().clone(),
+            );
+            Reverse(
+                npf_manager
+                    .manifest_of(&short_name)
+                    .and_then(last_update)
+                    .cloned(),
+            )
+        }),
+        // Files whose name matches the query best come first, then those with the shortest path
+        SortKey::Relevance => results.sort_by_key(|result| {
+            let file_name = result
+                .path()
+                .file_name()
+                .and_then(|name| name.to_str())
+                .unwrap_or("");
+            (
+                name_relevance(file_name, query),
+                result.path().as_os_str().len(),
+                result.path().to_path_buf(),
+            )
+        }),
+    }
+}
@@ -1,16 +1,266 @@
+use std::io::Cursor;
 use std::sync::{Arc, RwLock};
 
-use libnest::package::PackageManifest;
-use rocket::State;
-use rocket_contrib::json::Json;
+use rocket::http::{ContentType, Status};
+use rocket::request::{self, FromRequest, Request};
+use rocket::response::{self, Responder};
+use rocket::{Outcome, Response, State};
+use rocket_contrib::json;
+use rocket_contrib::json::JsonValue;
+use serde_json::Value;
 
+use crate::api::page::{self, Page, SortKey};
+use crate::package::changes::IndexRevision;
+use crate::package::snapshot::{Encoding, IndexSnapshot};
 use crate::package::NPFManager;
+use crate::param::{RevisionParam, SortParam};
+
+/// The entity tags given by the `If-None-Match` header of the request, if any
+pub struct IfNoneMatch(Vec<String>);
+
+impl IfNoneMatch {
+    /// Test if the given entity tag is one of those of the header, using the weak comparison
+    pub fn matches(&self, etag: &str) -> bool {
+        let etag = etag.trim_start_matches("W/");
+        self.0
+            .iter()
+            .any(|tag| tag == "*" || tag.trim_start_matches("W/") == etag)
+    }
+}
+
+impl<'a, 'r> FromRequest<'a, 'r> for IfNoneMatch {
+    type Error = ();
+
+    fn from_request(request: &'a Request<'r>) -> request::Outcome<IfNoneMatch, ()> {
+        let tags = request
+            .headers()
+            .get("If-None-Match")
+            .flat_map(|header| header.split(','))
+            .map(|tag| tag.trim().to_string())
+            .filter(|tag| !tag.is_empty())
+            .collect();
+        Outcome::Success(IfNoneMatch(tags))
+    }
+}
+
+/// The encoding to use for the response, negotiated with the `Accept-Encoding` header of the request.
+///
+/// The encoding with the highest quality value is used. On a tie, zstd is preferred over gzip, which is preferred
+/// over no encoding at all.
+pub struct AcceptEncoding(Encoding);
+
+/// The encodings of the snapshot, in order of preference
+static PREFERRED_ENCODINGS: [Encoding; 3] = [Encoding::Zstd, Encoding::Gzip, Encoding::Identity];
+
+impl<'a, 'r> FromRequest<'a, 'r> for AcceptEncoding {
+    type Error = ();
+
+    fn from_request(request: &'a Request<'r>) -> request::Outcome<AcceptEncoding, ()> {
+        let accepted = request
+            .headers()
+            .get("Accept-Encoding")
+            .flat_map(|header| header.split(','))
+            .filter_map(|coding| {
+                let mut parts = coding.split(';').map(str::trim);
+                let name = parts.next().filter(|name| !name.is_empty())?.to_lowercase();
+                let quality = parts
+                    .filter_map(|param| {
+                        let mut param = param.splitn(2, '=').map(str::trim);
+                        match (param.next(), param.next()) {
+                            (Some(key), Some(value)) if key.eq_ignore_ascii_case("q") => {
+                                value.parse::<f32>().ok()
+                            }
+                            _ => None,
+                        }
+                    })
+                    .next()
+                    .unwrap_or(1.0);
+
+                Some((name, quality))
+            })
+            .collect::<Vec<_>>();
+
+        // An encoding that isn't listed gets the quality of "*", if any. No encoding at all is acceptable unless
+        // explicitly refused.
+        let quality_of = |encoding: Encoding| {
+            let quality = |name: &str| {
+                accepted
+                    .iter()
+                    .find(|(coding, _)| coding == name)
+                    .map(|(_, quality)| *quality)
+            };
+            quality(encoding.name()).or_else(|| quality("*")).unwrap_or(
+                if encoding == Encoding::Identity {
+                    1.0
+                } else {
+                    0.0
+                },
+            )
+        };
+
+        let mut encoding = Encoding::Identity;
+        let mut best = 0.0;
+        for candidate in PREFERRED_ENCODINGS.iter() {
+            let quality = quality_of(*candidate);
+            if quality > best {
+                encoding = *candidate;
+                best = quality;
+            }
+        }
+        Outcome::Success(AcceptEncoding(encoding))
+    }
+}
+
+/// The prebuilt snapshot of the index, in one of its encodings
+pub struct SnapshotResponse {
+    snapshot: Arc<IndexSnapshot>,
+    encoding: Encoding,
+}
+
+/// The bytes of a snapshot in a given encoding, shared with the [`NPFManager`] instead of copied
+struct SnapshotBody(Arc<IndexSnapshot>, Encoding);
+
+impl AsRef<[u8]> for SnapshotBody {
+    fn as_ref(&self) -> &[u8] {
+        self.0.encoded(self.1)
+    }
+}
+
+impl<'r> Responder<'r> for SnapshotResponse {
+    fn respond_to(self, _: &Request) -> response::Result<'r> {
+        let mut response = Response::build();
+
+        response
+            .header(ContentType::JSON)
+            .raw_header("X-Total-Count", self.snapshot.count().to_string())
+            .raw_header("X-Offset", "0");
+        if let Some(content_encoding) = self.encoding.header_value() {
+            response.raw_header("Content-Encoding", content_encoding);
+        }
+
+        response
+            .sized_body(Cursor::new(SnapshotBody(self.snapshot, self.encoding)))
+            .ok()
+    }
+}
+
+/// The entity tag of a response computed from the given revision of the index.
+///
+/// The snapshot of the index has a strong tag per encoding, as their bytes differ. Other responses are never encoded,
+/// so they only get a weak tag.
+pub fn etag(revision: IndexRevision, encoding: Option<Encoding>) -> String {
+    match encoding {
+        Some(encoding) => format!("\"{}-{}\"", revision, encoding.name()),
+        None => format!("W/\"{}\"", revision),
+    }
+}
+
+/// A response tagged with the revision of the index it was computed from
+pub struct Revisioned<R> {
+    response: Option<R>,
+    revision: IndexRevision,
+    etag: String,
+}
+
+impl<R> Revisioned<R> {
+    /// Build a response, unless the client already has this representation of this revision of the index,
+    /// in which case `304 Not Modified` is sent
+    pub fn new<F: FnOnce() -> R>(
+        revision: IndexRevision,
+        encoding: Option<Encoding>,
+        if_none_match: &IfNoneMatch,
+        response: F,
+    ) -> Self {
+        let etag = etag(revision, encoding);
+        let response = if if_none_match.matches(&etag) {
+            None
+        } else {
+            Some(response())
+        };
+
+        Self {
+            response,
+            revision,
+            etag,
+        }
+    }
+}
+
+impl<'r, R: Responder<'r>> Responder<'r> for Revisioned<R> {
+    fn respond_to(self, req: &Request) -> response::Result<'r> {
+        let mut response = match self.response {
+            Some(response) => Response::build_from(response.respond_to(req)?),
+            None => {
+                let mut response = Response::build();
+                response.status(Status::NotModified);
+                response
+            }
+        };
+
+        response
+            .raw_header("ETag", self.etag)
+            .raw_header("Vary", "Accept-Encoding")
+            .raw_header("X-Index-Revision", self.revision.to_string())
+            .ok()
+    }
+}
+
+/// The whole index, or its changes since a given revision
+#[derive(Responder)]
+pub enum PullResponse {
+    Snapshot(SnapshotResponse),
+    Index(Page<Value>),
+    Changes(JsonValue),
+}
+
+#[get("/api/pull?<offset>&<limit>&<sort>&<since>")]
+pub fn pull(
+    npf_manager: State<Arc<RwLock<NPFManager>>>,
+    if_none_match: IfNoneMatch,
+    accept_encoding: AcceptEncoding,
+    offset: Option<usize>,
+    limit: Option<usize>,
+    sort: Option<SortParam>,
+    since: Option<RevisionParam>,
+) -> Revisioned<PullResponse> {
+    // The whole index is served from its snapshot, holding the lock only to get it
+    if offset.is_none() && limit.is_none() && sort.is_none() && since.is_none() {
+        let snapshot = npf_manager
+            .read()
+            .expect("can't open the NPF manager in read-only mode")
+            .snapshot();
+
+        if let Some(snapshot) = snapshot {
+            return Revisioned::new(
+                snapshot.revision(),
+                Some(accept_encoding.0),
+                &if_none_match,
+                || {
+                    PullResponse::Snapshot(SnapshotResponse {
+                        snapshot,
+                        encoding: accept_encoding.0,
+                    })
+                },
+            );
+        }
+    }
 
-#[get("/api/pull")]
-pub fn pull(npf_manager: State<Arc<RwLock<NPFManager>>>) -> Json<Vec<PackageManifest>> {
     let npf_manager = npf_manager
         .read()
         .expect("can't open the NPF manager in read-only mode");
 
-    Json(npf_manager.manifests().cloned().collect::<Vec<_>>())
+    Revisioned::new(npf_manager.revision(), None, &if_none_match, || {
+        if let Some(since) = since {
+            return PullResponse::Changes(json!(npf_manager.changes_since(since.into())));
+        }
+
+        let mut manifests = npf_manager.manifests().collect::<Vec<_>>();
+        let sort = sort.map_or(SortKey::Name, Into::into);
+        page::sort_manifests(&mut manifests, sort, "");
+
+        PullResponse::Index(
+            Page::paginate(manifests, offset, limit)
+                .map(|manifest| npf_manager.index_entry_of(manifest)),
+        )
+    })
 }
@@ -0,0 +1,27 @@
+use std::sync::{Arc, RwLock};
+
+use rocket::State;
+use rocket_contrib::json::Json;
+
+use crate::api::auth::AuthToken;
+use crate::api::error::ApiError;
+use crate::package::consistency::DependencyReport;
+use crate::package::NPFManager;
+
+#[get("/api/report/dependencies")]
+pub fn dependencies(
+    npf_manager: State<Arc<RwLock<NPFManager>>>,
+    token: AuthToken,
+) -> Result<Json<DependencyReport>, ApiError> {
+    if !token.is_admin() {
+        return Err(ApiError::forbidden(
+            "only admin tokens can browse the dependency report",
+        ));
+    }
+
+    let npf_manager = npf_manager
+        .read()
+        .expect("can't open the NPF manager in read-only mode");
+
+    Ok(Json(npf_manager.dependency_report()))
+}
@@ -0,0 +1,38 @@
+use std::sync::{Arc, RwLock};
+
+use libnest::package::PackageRequirement;
+use rocket::http::Status;
+use rocket::State;
+use rocket_contrib::json::Json;
+
+use crate::api::error::ApiError;
+use crate::config::Config;
+use crate::package::resolve::{self, Resolution, ResolutionError};
+use crate::package::NPFManager;
+
+#[post("/api/resolve", data = "<requirements>")]
+pub fn resolve(
+    config: State<Arc<Config>>,
+    npf_manager: State<Arc<RwLock<NPFManager>>>,
+    requirements: Json<Vec<PackageRequirement>>,
+) -> Result<Json<Resolution>, ApiError> {
+    // The NPF manager is only locked to fetch each manifest, so that a long resolution doesn't block its updates
+    let fetch = |short_name: &_| {
+        npf_manager
+            .read()
+            .expect("can't open the NPF manager in read-only mode")
+            .manifest_of(short_name)
+            .cloned()
+    };
+
+    resolve::resolve(config.name(), fetch, &requirements)
+        .map(Json)
+        .map_err(|e| match e {
+            ResolutionError::Conflict(_) => {
+                ApiError::new(Status::Conflict, "dependency_conflict", e.to_string())
+            }
+            ResolutionError::TooComplex(_) => {
+                ApiError::new(Status::Conflict, "resolution_too_complex", e.to_string())
+            }
+        })
+}
@@ -1,26 +1,53 @@
 use std::sync::{Arc, RwLock, RwLockReadGuard};
 
+use failure::format_err;
 use libnest::package::PackageManifest;
+use rocket::http::Status;
 use rocket::State;
-use rocket_contrib::json::Json;
+use serde_json::Value;
 
+use crate::api::error::ApiError;
+use crate::api::page::{self, Page, SortKey};
+use crate::package::file_index::{FileQuery, Position, Target};
 use crate::package::{ContentSearchResult, NPFManager};
+use crate::param::{PositionParam, SortParam};
 
-#[get("/api/search?<q>&<exact_match>&search_by=content")]
+#[get("/api/search?<q>&<exact_match>&<mode>&<position>&<offset>&<limit>&<sort>&search_by=content")]
 pub fn search_content(
     npf_manager: State<Arc<RwLock<NPFManager>>>,
     q: String,
     exact_match: Option<bool>,
-) -> Json<Vec<ContentSearchResult>> {
+    mode: Option<String>,
+    position: Option<PositionParam>,
+    offset: Option<usize>,
+    limit: Option<usize>,
+    sort: Option<SortParam>,
+) -> Result<Page<ContentSearchResult>, ApiError> {
     let npf_manager = npf_manager
         .read()
         .expect("can't open the NPF manager in read-only mode");
 
-    Json(
-        npf_manager
-            .browse_packages_for_file(&q, exact_match.unwrap_or_default())
-            .unwrap_or_default(),
-    )
+    // `position` supersedes `exact_match`, which is kept for compatibility
+    let position = match (position, exact_match) {
+        (Some(position), _) => position.into(),
+        (None, Some(true)) => Position::Exact,
+        _ => Position::Substring,
+    };
+
+    let query = match mode.as_ref().map(String::as_str).unwrap_or("name") {
+        "name" => FileQuery::text(&q, Target::Name, position),
+        "path" => FileQuery::text(&q, Target::Path, position),
+        "glob" => FileQuery::glob(&q),
+        "regex" => FileQuery::regex(&q),
+        mode => Err(format_err!("{}: invalid search mode", mode)),
+    }
+    .map_err(|e| ApiError::from_failure(Status::BadRequest, "invalid_query", &e))?;
+
+    let (mut results, truncated) = npf_manager.search_files(&query);
+    let sort = sort.map_or(SortKey::Relevance, Into::into);
+    page::sort_content_results(&mut results, sort, &q, &npf_manager);
+
+    Ok(Page::paginate(results, offset, limit).truncated(truncated))
 }
 
 pub fn do_search_metadata<'a>(
@@ -62,26 +89,51 @@ pub fn do_search_metadata<'a>(
     }
 }
 
-#[get("/api/search?<q>&<search_by>&<exact_match>")]
+/// The results of a search on the metadata of packages
+#[derive(Responder)]
+pub enum MetadataSearchResults {
+    Manifests(Page<PackageManifest>),
+    Hits(Page<Value>),
+}
+
+#[get("/api/search?<q>&<search_by>&<exact_match>&<offset>&<limit>&<sort>")]
 pub fn search_metadata(
     npf_manager: State<Arc<RwLock<NPFManager>>>,
     q: String,
-    search_by: String,
+    search_by: Option<String>,
     exact_match: Option<bool>,
-) -> Json<Vec<PackageManifest>> {
+    offset: Option<usize>,
+    limit: Option<usize>,
+    sort: Option<SortParam>,
+) -> Result<MetadataSearchResults, ApiError> {
     let npf_manager = npf_manager
         .read()
         .expect("can't open the NPF manager in read-only mode");
+    let search_by = search_by.unwrap_or_else(|| String::from("all"));
+
+    // Full-text searches return the score of each hit along with the manifest
+    if search_by == "all" {
+        let mut hits = npf_manager.search_fulltext(&q);
+        if let Some(sort) = sort {
+            page::sort_by_manifest(&mut hits, sort.into(), |hit| hit.manifest());
+        }
+
+        let hits = Page::paginate(hits, offset, limit)
+            .try_map(serde_json::to_value)
+            .map_err(|e| ApiError::internal(&e.into()))?;
+        return Ok(MetadataSearchResults::Hits(hits));
+    }
+
+    let mut results = do_search_metadata(
+        &npf_manager,
+        &q,
+        &search_by,
+        exact_match.unwrap_or_default(),
+    );
+    let sort = sort.map_or(SortKey::Relevance, Into::into);
+    page::sort_manifests(&mut results, sort, &q);
 
-    Json(
-        do_search_metadata(
-            &npf_manager,
-            &q,
-            &search_by,
-            exact_match.unwrap_or_default(),
-        )
-        .into_iter()
-        .cloned()
-        .collect(),
-    )
+    Ok(MetadataSearchResults::Manifests(
+        Page::paginate(results, offset, limit).map(PackageManifest::clone),
+    ))
 }
@@ -0,0 +1,51 @@
+use std::sync::{Arc, RwLock};
+
+use rocket::http::Status;
+use rocket::State;
+use rocket_contrib::json::Json;
+
+use crate::api::error::ApiError;
+use crate::package::signing::{KeyRing, PublicKeyInfo, SignedIndex};
+use crate::package::NPFManager;
+
+#[get("/api/pull.sig")]
+pub fn pull_sig(
+    npf_manager: State<Arc<RwLock<NPFManager>>>,
+) -> Result<Json<SignedIndex>, ApiError> {
+    let is_outdated = npf_manager
+        .read()
+        .expect("can't open the NPF manager in read-only mode")
+        .is_signature_outdated();
+
+    // Keys expire while the index doesn't change, so they are checked each time the signature is served
+    if is_outdated {
+        let mut npf_manager = npf_manager
+            .write()
+            .expect("can't open the NPF manager in read-write mode");
+        if npf_manager.is_signature_outdated() {
+            npf_manager
+                .resign_index()
+                .map_err(|e| ApiError::internal(&e))?;
+        }
+    }
+
+    let signed_index = npf_manager
+        .read()
+        .expect("can't open the NPF manager in read-only mode")
+        .signed_index();
+
+    signed_index
+        .map(|signed_index| Json(signed_index.as_ref().clone()))
+        .ok_or_else(|| {
+            ApiError::new(
+                Status::NotFound,
+                "not_signed",
+                "the index of this repository isn't signed",
+            )
+        })
+}
+
+#[get("/api/key")]
+pub fn key(key_ring: State<Arc<KeyRing>>) -> Json<Vec<PublicKeyInfo>> {
+    Json(key_ring.public_keys())
+}
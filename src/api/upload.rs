@@ -1,63 +1,349 @@
 use std::fs::{self, File};
-use std::io;
-use std::path::PathBuf;
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
 
+use data_encoding::HEXUPPER;
 use failure::Error;
-use libnest::package::NPFExplorer;
+use libnest::package::{NPFExplorer, PackageID};
 use rocket::http::Status;
-use rocket::{Data, State};
+use rocket::request::{self, FromRequest, Request};
+use rocket::{Data, Outcome, State};
 use rocket_contrib::json;
 use rocket_contrib::json::JsonValue;
+use sha2::{Digest, Sha256, Sha512};
 
 use crate::api::auth::AuthToken;
-use crate::config::Config;
+use crate::api::error::ApiError;
+use crate::audit::{AuditEntry, AuditLog, AuditOutcome, RequestOrigin};
+use crate::config::{Config, SafetyPolicy, Scope};
 use crate::package::gen_tmp_filename;
+use crate::package::limits::LimitExceededError;
+use crate::package::safety;
+use crate::package::signature;
+use crate::package::validation::{self, ValidationProblem};
 
-#[post("/api/upload", data = "<data>")]
+/// The size announced by the `Content-Length` header of the request, if any
+pub struct ContentLength(Option<u64>);
+
+impl<'a, 'r> FromRequest<'a, 'r> for ContentLength {
+    type Error = ();
+
+    fn from_request(request: &'a Request<'r>) -> request::Outcome<ContentLength, ()> {
+        let len = request
+            .headers()
+            .get_one("Content-Length")
+            .and_then(|len| len.parse().ok());
+        Outcome::Success(ContentLength(len))
+    }
+}
+
+/// The detached signature of the NPF given by the `X-Signature` header of the request, if any
+pub struct SignatureHeader(Option<String>);
+
+impl<'a, 'r> FromRequest<'a, 'r> for SignatureHeader {
+    type Error = ();
+
+    fn from_request(request: &'a Request<'r>) -> request::Outcome<SignatureHeader, ()> {
+        let signature = request
+            .headers()
+            .get_one("X-Signature")
+            .map(|signature| signature.trim().to_string())
+            .filter(|signature| !signature.is_empty());
+        Outcome::Success(SignatureHeader(signature))
+    }
+}
+
+/// The error returned when the package has validation problems
+fn invalid_package(problems: &[ValidationProblem]) -> ApiError {
+    ApiError::new(
+        Status::UnprocessableEntity,
+        "invalid_package",
+        "the package is invalid",
+    )
+    .details(serde_json::json!({ "problems": problems }))
+}
+
+/// The error returned when the uploaded version is already published and can't be overwritten
+fn version_exists() -> ApiError {
+    ApiError::new(
+        Status::Conflict,
+        "version_exists",
+        "this version of the package already exists",
+    )
+}
+
+/// Ensure the given directories have enough space left to receive an upload of the given size
+fn check_free_space(config: &Config, dirs: &[&Path], size: u64) -> Result<(), ApiError> {
+    let needed = size + config.min_free_space();
+
+    for dir in dirs {
+        let available = fs2::available_space(dir).map_err(|e| ApiError::internal(&e.into()))?;
+        if available < needed {
+            Err(ApiError::new(
+                Status::InsufficientStorage,
+                "insufficient_storage",
+                format!("not enough disk space left in {}", dir.display()),
+            ))?;
+        }
+    }
+    Ok(())
+}
+
+#[post("/api/upload?<force>", data = "<data>")]
 pub fn upload(
     data: Data,
     config: State<Arc<Config>>,
-    _token: AuthToken,
-) -> Result<JsonValue, Status> {
-    let tmp_path = gen_tmp_filename();
+    audit_log: State<Arc<AuditLog>>,
+    origin: RequestOrigin,
+    token: AuthToken,
+    content_length: ContentLength,
+    signature_header: SignatureHeader,
+    force: Option<bool>,
+) -> Result<JsonValue, ApiError> {
+    // The NPF is written in the staging directory, on the same file system than `package_dir`,
+    // so that it can be atomically moved to its final destination once it is validated.
+    let tmp_path = gen_tmp_filename(&config.staging_dir());
+    let tmp_sig_path = signature::signature_path(&tmp_path);
+    let mut sha256 = None;
+    let mut sha512 = None;
+    let mut signer = None;
+    let mut id = None;
+    let mut note = None;
 
-    let r: Result<JsonValue, Error> = try {
-        // Write data to /tmp/nest-server/
-        if let Some(parent) = tmp_path.parent() {
-            fs::create_dir_all(parent)?;
+    let r: Result<JsonValue, ApiError> = try {
+        // Refuse tokens that can't upload anything before reading the NPF.
+        // The category is only known once it is read, so it is checked later on.
+        if !token.is_allowed_anywhere(Scope::Upload) {
+            Err(ApiError::forbidden(
+                "the token isn't allowed to upload packages",
+            ))?;
         }
 
-        let mut file = File::create(&tmp_path)?;
-        io::copy(&mut data.open(), &mut file)?;
+        let max_upload_size = config.limits().max_upload_size();
+
+        // Refuse NPFs that are obviously too big without reading them
+        if content_length.0.map_or(false, |len| len > max_upload_size) {
+            Err(ApiError::payload_too_large(&LimitExceededError::new(
+                "max_upload_size",
+                max_upload_size,
+            )))?;
+        }
+
+        // Refuse the upload early rather than filling up the disk. The NPF is extracted
+        // in `tmp_dir`, so its size is a rough estimate of what will be needed there too.
+        check_free_space(
+            &config,
+            &[&config.staging_dir(), config.tmp_dir()],
+            content_length.0.unwrap_or(0),
+        )?;
+
+        let written: Result<(), Error> = try {
+            // Write data to `<package_dir>/.staging/`
+            if let Some(parent) = tmp_path.parent() {
+                fs::create_dir_all(parent)?;
+            }
 
-        let npf_explorer = NPFExplorer::open_at(&tmp_path, "/var/tmp/nest-server")?;
-        let id = npf_explorer.manifest().id(config.name().clone());
+            // Compute the SHA-256 of the NPF while it is written, for the audit log,
+            // and its SHA-512, against which its signature is checked
+            let mut file = File::create(&tmp_path)?;
+            let mut hasher = Sha256::default();
+            let mut prehashed = Sha512::default();
+            let mut stream = data.open();
+            let mut buffer = [0u8; 8192];
+            let mut total = 0;
+
+            loop {
+                let len = stream.read(&mut buffer)?;
+                if len == 0 {
+                    break;
+                }
+
+                // The `Content-Length` header may be missing or lying, so the size is also checked here
+                total += len as u64;
+                if total > max_upload_size {
+                    Err(LimitExceededError::new("max_upload_size", max_upload_size))?;
+                }
+                hasher.input(&buffer[..len]);
+                prehashed.input(&buffer[..len]);
+                file.write_all(&buffer[..len])?;
+            }
+            file.sync_all()?;
+            sha256 = Some(HEXUPPER.encode(hasher.result().as_ref()));
+            sha512 = Some(prehashed);
+        };
+        written.map_err(|e| match e.downcast_ref::<LimitExceededError>() {
+            Some(e) => ApiError::payload_too_large(e),
+            None => ApiError::from_failure(Status::InternalServerError, "upload_failed", &e),
+        })?;
+
+        // The manifest is validated on its raw form first, as a malformed one can't even be explored
+        let manifest_problems =
+            validation::validate_manifest(&tmp_path).map_err(|e| ApiError::from_npf(&e))?;
+
+        let npf: Result<(NPFExplorer, PackageID), Error> = try {
+            let npf_explorer = NPFExplorer::open_at(&tmp_path, config.tmp_dir())?;
+            let npf_id = npf_explorer.manifest().id(config.name().clone());
+            (npf_explorer, npf_id)
+        };
+        let (npf_explorer, npf_id) = npf.map_err(|e| {
+            if manifest_problems.is_empty() {
+                ApiError::from_npf(&e)
+            } else {
+                invalid_package(&manifest_problems)
+            }
+        })?;
+        id = Some(npf_id.clone());
+
+        // Ensure the token is allowed to upload packages in this category
+        if !token.is_allowed(Scope::Upload, npf_id.category()) {
+            Err(ApiError::forbidden(
+                "the token isn't allowed to upload in this category",
+            ))?;
+        }
+
+        // Ensure the signature, if any, was made by a maintainer allowed to sign this package
+        if let (Some(signature), Some(sha512)) = (&signature_header.0, &sha512) {
+            let manifest = npf_explorer.manifest();
+            let verified = signature::verify(
+                &config,
+                manifest.category(),
+                manifest.metadata().maintainer(),
+                sha512,
+                signature,
+            )
+            .map_err(|e| {
+                ApiError::new(
+                    Status::UnprocessableEntity,
+                    "invalid_signature",
+                    e.to_string(),
+                )
+            })?;
+            signer = Some(verified);
+        }
 
         // Move file to its final destination: `./cache/<category>/<package>/<name>-<version>.nest`.
         let dst_path = PathBuf::from(config.package_dir())
-            .join(id.category().as_ref())
-            .join(id.name().as_ref())
-            .join(format!("{}-{}.nest", id.name(), id.version().to_string()));
+            .join(npf_id.category().as_ref())
+            .join(npf_id.name().as_ref())
+            .join(format!(
+                "{}-{}.nest",
+                npf_id.name(),
+                npf_id.version().to_string()
+            ));
+
+        // Published versions are immutable unless the repository says otherwise, or an admin forces the upload
+        let mut may_overwrite = !config.immutable_versions();
+        if dst_path.exists() && config.immutable_versions() {
+            if !force.unwrap_or_default() {
+                Err(version_exists())?;
+            } else if !token.is_admin() {
+                Err(ApiError::forbidden(
+                    "only admin tokens can overwrite an existing version",
+                ))?;
+            }
+            may_overwrite = true;
+            note = Some("forced overwrite of an existing version");
+        }
+
+        // Refuse to publish packages whose content is too big, before it fills the cache, and packages that
+        // are unsafe to install, unless the repository only flags them
+        let findings =
+            safety::scan(&npf_explorer, config.limits()).map_err(|e| ApiError::from_npf(&e))?;
+        if !findings.is_empty() && config.safety_policy() == SafetyPolicy::Reject {
+            Err(ApiError::new(
+                Status::UnprocessableEntity,
+                "unsafe_package",
+                "the package contains unsafe entries",
+            )
+            .details(serde_json::json!({ "findings": findings })))?;
+        }
 
-        if let Some(parent) = dst_path.parent() {
-            fs::create_dir_all(parent)?;
+        // Refuse to publish invalid packages
+        let mut problems = manifest_problems;
+        problems.extend(
+            validation::validate(&npf_explorer)
+                .map_err(|e| ApiError::from_failure(Status::BadRequest, "invalid_npf", &e))?,
+        );
+        if !problems.is_empty() {
+            Err(invalid_package(&problems))?;
         }
 
-        // We do a copy and not a rename because `tmp_path` and `dst_path` may not share the same mountpoint
-        // The file system notifier will finish the job and update the cache
-        fs::copy(&tmp_path, &dst_path)?;
+        let published: Result<(), Error> = try {
+            if let Some(parent) = dst_path.parent() {
+                fs::create_dir_all(parent)?;
+            }
+
+            // The signature is staged along with the NPF, and only published once the NPF is
+            if let (Some(signature), Some(_)) = (&signature_header.0, &signer) {
+                fs::write(&tmp_sig_path, signature)?;
+            }
+
+            // Both are atomic, so readers never observe a partially written NPF. Unlike a rename, a hard link fails
+            // if the destination exists, so a concurrent upload of the same version can't be silently replaced.
+            // The file system notifier will finish the job and update the cache
+            if may_overwrite {
+                fs::rename(&tmp_path, &dst_path)?;
+            } else {
+                fs::hard_link(&tmp_path, &dst_path)?;
+            }
+        };
+        published.map_err(|e| match e.downcast_ref::<io::Error>() {
+            Some(e) if e.kind() == io::ErrorKind::AlreadyExists => version_exists(),
+            _ => ApiError::from_failure(Status::InternalServerError, "publish_failed", &e),
+        })?;
+
+        // A signature left by a previous upload of this version doesn't apply to this one.
+        // The cache checks the signature again when it changes.
+        let signed: Result<(), Error> = try {
+            let sig_path = signature::signature_path(&dst_path);
+            if tmp_sig_path.exists() {
+                fs::rename(&tmp_sig_path, &sig_path)?;
+            } else if sig_path.exists() {
+                fs::remove_file(&sig_path)?;
+            }
+        };
+        signed.map_err(|e| {
+            ApiError::from_failure(Status::InternalServerError, "publish_failed", &e)
+        })?;
 
         json!({
-            "name": id.name(),
-            "category": id.category(),
-            "version": id.version(),
+            "name": npf_id.name(),
+            "category": npf_id.category(),
+            "version": npf_id.version(),
+            "findings": findings,
+            "signer": signer,
         })
     };
 
-    if tmp_path.exists() {
-        let _ = fs::remove_file(tmp_path);
+    for path in &[&tmp_path, &tmp_sig_path] {
+        if path.exists() {
+            let _ = fs::remove_file(path);
+        }
+    }
+
+    // Record the upload attempt, whatever its outcome
+    let outcome = if r.is_ok() {
+        AuditOutcome::Success
+    } else {
+        AuditOutcome::Failure
+    };
+    let mut entry = AuditEntry::new(&origin, outcome).identity(token.identity());
+    if let Some(id) = &id {
+        entry = entry.package(id);
+    }
+    if let Some(sha256) = &sha256 {
+        entry = entry.sha256(sha256);
+    }
+    if let Some(signer) = &signer {
+        entry = entry.signer(signer.name());
+    }
+    if let Err(e) = &r {
+        entry = entry.details(e.message());
+    } else if let Some(note) = note {
+        entry = entry.details(note);
     }
+    audit_log.record(entry);
 
-    r.map_err(|_| Status::BadRequest)
+    r
 }
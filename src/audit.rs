@@ -0,0 +1,244 @@
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::net::IpAddr;
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+use chrono::{DateTime, Utc};
+use failure::{Error, ResultExt};
+use libnest::package::PackageID;
+use rocket::request::{self, FromRequest, Request};
+use rocket::Outcome;
+use serde::{Deserialize, Serialize};
+
+use crate::config::Config;
+
+/// Where a request comes from and which route it targets, as recorded in the audit log.
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub struct RequestOrigin {
+    ip: Option<IpAddr>,
+    route: String,
+}
+
+impl RequestOrigin {
+    pub fn from(request: &Request) -> Self {
+        Self {
+            ip: request.client_ip(),
+            route: format!("{} {}", request.method(), request.uri().path()),
+        }
+    }
+}
+
+impl<'a, 'r> FromRequest<'a, 'r> for RequestOrigin {
+    type Error = ();
+
+    fn from_request(request: &'a Request<'r>) -> request::Outcome<RequestOrigin, ()> {
+        Outcome::Success(RequestOrigin::from(request))
+    }
+}
+
+#[derive(Serialize, Deserialize, Copy, Clone, Eq, PartialEq, Hash, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum AuditOutcome {
+    Success,
+    Failure,
+}
+
+/// The package an audit entry is about.
+#[derive(Serialize, Deserialize, Clone, Eq, PartialEq, Hash, Debug)]
+pub struct AuditPackage {
+    category: String,
+    name: String,
+    version: String,
+}
+
+impl AuditPackage {
+    pub fn from(id: &PackageID) -> Self {
+        Self {
+            category: id.category().to_string(),
+            name: id.name().to_string(),
+            version: id.version().to_string(),
+        }
+    }
+}
+
+/// A single line of the audit log
+#[derive(Serialize, Deserialize, Clone, Eq, PartialEq, Debug)]
+pub struct AuditEntry {
+    date: DateTime<Utc>,
+    identity: Option<String>,
+    ip: Option<IpAddr>,
+    route: String,
+    package: Option<AuditPackage>,
+    sha256: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    signer: Option<String>,
+    outcome: AuditOutcome,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    details: Option<String>,
+}
+
+impl AuditEntry {
+    pub fn new(origin: &RequestOrigin, outcome: AuditOutcome) -> Self {
+        Self {
+            date: Utc::now(),
+            identity: None,
+            ip: origin.ip,
+            route: origin.route.clone(),
+            package: None,
+            sha256: None,
+            signer: None,
+            outcome,
+            details: None,
+        }
+    }
+
+    pub fn identity(mut self, identity: &str) -> Self {
+        self.identity = Some(identity.to_string());
+        self
+    }
+
+    pub fn package(mut self, id: &PackageID) -> Self {
+        self.package = Some(AuditPackage::from(id));
+        self
+    }
+
+    pub fn outcome(mut self, outcome: AuditOutcome) -> Self {
+        self.outcome = outcome;
+        self
+    }
+
+    pub fn sha256(mut self, sha256: &str) -> Self {
+        self.sha256 = Some(sha256.to_string());
+        self
+    }
+
+    /// The name of the maintainer who signed the uploaded NPF
+    pub fn signer(mut self, signer: &str) -> Self {
+        self.signer = Some(signer.to_string());
+        self
+    }
+
+    pub fn details<S: Into<String>>(mut self, details: S) -> Self {
+        self.details = Some(details.into());
+        self
+    }
+
+    pub fn date(&self) -> &DateTime<Utc> {
+        &self.date
+    }
+
+    /// Test if the entry concerns the given package.
+    ///
+    /// Any of the given parts that is `None` matches all packages.
+    pub fn is_about(
+        &self,
+        category: Option<&str>,
+        name: Option<&str>,
+        version: Option<&str>,
+    ) -> bool {
+        if category.is_none() && name.is_none() && version.is_none() {
+            return true;
+        }
+
+        self.package.as_ref().map_or(false, |package| {
+            category.map_or(true, |category| package.category == category)
+                && name.map_or(true, |name| package.name == name)
+                && version.map_or(true, |version| package.version == version)
+        })
+    }
+}
+
+/// An append-only log of all the sensitive operations done on the repository.
+///
+/// Entries are stored as JSON lines in `<audit_dir>/audit.log`.
+pub struct AuditLog {
+    path: PathBuf,
+    file: Mutex<File>,
+}
+
+impl AuditLog {
+    pub fn open(config: &Config) -> Result<Self, Error> {
+        let path = config.audit_dir().join("audit.log");
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&path)
+            .context(path.display().to_string())?;
+
+        Ok(Self {
+            path,
+            file: Mutex::new(file),
+        })
+    }
+
+    /// Append an entry to the log.
+    ///
+    /// Failing to write the log doesn't prevent the operation from happening, but the failure is reported.
+    pub fn record(&self, entry: AuditEntry) {
+        let r: Result<(), Error> = try {
+            let mut line = serde_json::to_vec(&entry)?;
+            line.push(b'\n');
+
+            let mut file = self
+                .file
+                .lock()
+                .expect("can't lock the audit log in write mode");
+            file.write_all(&line)?;
+            file.flush()?;
+        };
+
+        if let Err(e) = r {
+            eprintln!("[AUDIT] Failed to record {:?}: {}", entry, e);
+        }
+    }
+
+    /// Return the entries of the log matching the given predicate, in chronological order.
+    ///
+    /// The first `offset` matching entries are skipped, and reading stops as soon as `limit` entries are found.
+    /// The returned boolean tells whether more matching entries follow.
+    ///
+    /// The log is read line by line. Lines that can't be parsed, like one left truncated by a crash, are reported and skipped.
+    pub fn entries<F>(
+        &self,
+        mut predicate: F,
+        offset: usize,
+        limit: usize,
+    ) -> Result<(Vec<AuditEntry>, bool), Error>
+    where
+        F: FnMut(&AuditEntry) -> bool,
+    {
+        let file = File::open(&self.path).context(self.path.display().to_string())?;
+        let mut entries = Vec::new();
+        let mut skipped = 0;
+
+        for (n, line) in BufReader::new(file).split(b'\n').enumerate() {
+            let line = line?;
+
+            if line.iter().all(u8::is_ascii_whitespace) {
+                continue;
+            }
+            match serde_json::from_slice::<AuditEntry>(&line) {
+                Ok(entry) => {
+                    if !predicate(&entry) {
+                        continue;
+                    }
+                    if skipped < offset {
+                        skipped += 1;
+                    } else if entries.len() < limit {
+                        entries.push(entry);
+                    } else {
+                        return Ok((entries, true));
+                    }
+                }
+                Err(e) => eprintln!(
+                    "[AUDIT] Skipping corrupt entry at line {} of \"{}\": {}",
+                    n + 1,
+                    self.path.display(),
+                    e
+                ),
+            }
+        }
+        Ok((entries, false))
+    }
+}
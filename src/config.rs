@@ -3,24 +3,54 @@ use std::fs::{self, File};
 use std::io::Read;
 use std::path::{Path, PathBuf};
 
+use chrono::{DateTime, Utc};
 use failure::{format_err, Error, ResultExt};
 use lazy_static::lazy_static;
-use libnest::package::RepositoryName;
+use libnest::package::{CategoryName, RepositoryName};
 use serde::{Deserialize, Serialize};
 
+use crate::api::auth::TokenHash;
+
 lazy_static! {
     static ref PATH_CONFIG: &'static Path = Path::new("./Repository.toml");
 }
 
+/// The prefix of the sub-directory of `tmp_dir` owned by an instance of Nest-Server.
+///
+/// It is followed by the name of the repository, so that instances serving different repositories never share it.
+static TMP_SUB_DIR_PREFIX: &str = "nest-server-";
+
 #[derive(Serialize, Deserialize, Clone, Eq, PartialEq, Hash, Debug)]
 pub struct Config {
     name: RepositoryName,
     pretty_name: String,
     package_dir: PathBuf,
     cache_dir: PathBuf,
-    links: Vec<Link>,
+    #[serde(default = "default_audit_dir")]
+    audit_dir: PathBuf,
+    #[serde(default = "default_tmp_dir")]
+    tmp_dir: PathBuf,
+    #[serde(default = "default_min_free_space")]
+    min_free_space: u64,
+    #[serde(default)]
+    limits: Limits,
     #[serde(default)]
+    safety_policy: SafetyPolicy,
+    #[serde(default = "default_max_search_results")]
+    max_search_results: usize,
+    #[serde(default)]
+    foreign_repositories: Vec<RepositoryName>,
+    links: Vec<Link>,
+    #[serde(default = "default_immutable_versions")]
+    immutable_versions: bool,
+    #[serde(default, skip_serializing)]
     auth_token: String,
+    #[serde(default)]
+    tokens: Vec<Token>,
+    #[serde(default)]
+    signing_keys: Vec<SigningKeyConfig>,
+    #[serde(default)]
+    maintainer_keys: Vec<MaintainerKey>,
 }
 
 impl Config {
@@ -46,8 +76,15 @@ impl Config {
         // All paths are canonicalized now so it's not needed to do it later
         let mut config: Config = toml::from_str(&s).context(path.display().to_string())?;
 
+        if let Some(value) = env::var_os("RAVEN_NEST_SERVER_TMP_DIR") {
+            config.tmp_dir = PathBuf::from(value);
+        }
+
         fs::create_dir_all(&config.package_dir)?;
+        fs::create_dir_all(config.staging_dir())?;
         fs::create_dir_all(&config.cache_dir)?;
+        fs::create_dir_all(&config.audit_dir)?;
+        fs::create_dir_all(&config.tmp_dir)?;
 
         config.package_dir = fs::canonicalize(&config.package_dir)
             .context(config.package_dir.display().to_string())?;
@@ -55,12 +92,51 @@ impl Config {
         config.cache_dir =
             fs::canonicalize(&config.cache_dir).context(config.cache_dir.display().to_string())?;
 
+        config.audit_dir =
+            fs::canonicalize(&config.audit_dir).context(config.audit_dir.display().to_string())?;
+
+        // Work in a sub-directory of `tmp_dir` of our own, so that emptying it on startup never removes files
+        // of other programs or of other instances
+        config.tmp_dir = fs::canonicalize(&config.tmp_dir)
+            .context(config.tmp_dir.display().to_string())?
+            .join(format!("{}{}", TMP_SUB_DIR_PREFIX, config.name.as_ref()));
+        fs::create_dir_all(&config.tmp_dir)?;
+
         if let Some(value) = env::var_os("RAVEN_NEST_SERVER_AUTH_TOKEN") {
             config.auth_token = value.to_string_lossy().to_string();
+        } else if !config.auth_token.is_empty() {
+            eprintln!("[CONFIG] \"auth_token\" is stored in plaintext, consider replacing it by a hashed token (see `nest-server hash-token`)");
+        }
+
+        // The legacy `auth_token` is turned into an unrestricted token named `default`.
+        // Only its digest is kept in memory.
+        if !config.auth_token.is_empty() {
+            config.tokens.push(Token {
+                name: String::from("default"),
+                hash: TokenHash::sha256(&config.auth_token),
+                scopes: vec![Scope::Admin],
+                categories: None,
+            });
+            config.auth_token.clear();
         }
 
-        if config.auth_token.is_empty() {
-            Err(format_err!("the authentication token is either empty or not present in both environment and configuration file"))?;
+        if config.tokens.is_empty() {
+            Err(format_err!(
+                "no authentication token is present in both environment and configuration file"
+            ))?;
+        }
+
+        // Tokens are looked up by their name, the part of the `X-Auth-Token` header before the first dot
+        for (i, token) in config.tokens.iter().enumerate() {
+            if token.name.contains('.') {
+                Err(format_err!(
+                    "{}: token names can't contain a dot",
+                    token.name
+                ))?;
+            }
+            if config.tokens[..i].iter().any(|t| t.name == token.name) {
+                Err(format_err!("{}: duplicated token name", token.name))?;
+            }
         }
 
         Ok(config)
@@ -78,16 +154,79 @@ impl Config {
         &self.package_dir
     }
 
+    /// The directory where uploads are written before being atomically moved to their final place.
+    ///
+    /// It is a hidden sub-directory of `package_dir` to ensure both are on the same file system.
+    pub fn staging_dir(&self) -> PathBuf {
+        self.package_dir.join(".staging")
+    }
+
     pub fn cache_dir(&self) -> &Path {
         &self.cache_dir
     }
 
+    pub fn audit_dir(&self) -> &Path {
+        &self.audit_dir
+    }
+
+    /// The directory used for temporary files, like NPFs being explored.
+    ///
+    /// It is the `nest-server-<name>` sub-directory of the configured `tmp_dir`, and its content is removed on startup.
+    pub fn tmp_dir(&self) -> &Path {
+        &self.tmp_dir
+    }
+
+    /// The amount of disk space, in bytes, that must remain available after an upload.
+    pub fn min_free_space(&self) -> u64 {
+        self.min_free_space
+    }
+
+    /// The limits enforced on uploaded NPFs and their content.
+    pub fn limits(&self) -> &Limits {
+        &self.limits
+    }
+
+    /// What to do with packages containing unsafe entries.
+    pub fn safety_policy(&self) -> SafetyPolicy {
+        self.safety_policy
+    }
+
+    /// The maximum number of files matched by a content search.
+    pub fn max_search_results(&self) -> usize {
+        self.max_search_results
+    }
+
+    /// The other repositories packages are allowed to depend on.
+    pub fn foreign_repositories(&self) -> &[RepositoryName] {
+        &self.foreign_repositories
+    }
+
+    /// Whether uploading an already published version of a package is refused.
+    pub fn immutable_versions(&self) -> bool {
+        self.immutable_versions
+    }
+
     pub fn links(&self) -> &[Link] {
         &self.links
     }
 
-    pub fn auth_token(&self) -> &str {
-        &self.auth_token
+    pub fn tokens(&self) -> &[Token] {
+        &self.tokens
+    }
+
+    /// Find the token with the given name.
+    pub fn token_named(&self, name: &str) -> Option<&Token> {
+        self.tokens.iter().find(|token| token.name == name)
+    }
+
+    /// The keys signing the index. Empty if the index isn't signed.
+    pub fn signing_keys(&self) -> &[SigningKeyConfig] {
+        &self.signing_keys
+    }
+
+    /// The public keys of the maintainers allowed to sign uploaded NPFs.
+    pub fn maintainer_keys(&self) -> &[MaintainerKey] {
+        &self.maintainer_keys
     }
 }
 
@@ -98,3 +237,188 @@ pub struct Link {
     #[serde(default)]
     active: bool,
 }
+
+fn default_audit_dir() -> PathBuf {
+    PathBuf::from("./audit/")
+}
+
+fn default_tmp_dir() -> PathBuf {
+    PathBuf::from("/var/tmp")
+}
+
+fn default_min_free_space() -> u64 {
+    64 * 1024 * 1024
+}
+
+fn default_max_search_results() -> usize {
+    1000
+}
+
+fn default_immutable_versions() -> bool {
+    true
+}
+
+/// Bounds on the resources an NPF may consume, as declared in the `[limits]` section of the configuration.
+#[derive(Serialize, Deserialize, Clone, Eq, PartialEq, Hash, Debug)]
+#[serde(default)]
+pub struct Limits {
+    max_upload_size: u64,
+    max_decompressed_size: u64,
+    max_entries: u64,
+    max_path_length: usize,
+}
+
+impl Limits {
+    /// The maximum size, in bytes, of an uploaded NPF.
+    pub fn max_upload_size(&self) -> u64 {
+        self.max_upload_size
+    }
+
+    /// The maximum size, in bytes, of the decompressed content of `data.tar.gz`.
+    pub fn max_decompressed_size(&self) -> u64 {
+        self.max_decompressed_size
+    }
+
+    /// The maximum number of entries in `data.tar.gz`.
+    pub fn max_entries(&self) -> u64 {
+        self.max_entries
+    }
+
+    /// The maximum length, in bytes, of the path of an entry of `data.tar.gz`.
+    pub fn max_path_length(&self) -> usize {
+        self.max_path_length
+    }
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Self {
+            max_upload_size: 1024 * 1024 * 1024,
+            max_decompressed_size: 4 * 1024 * 1024 * 1024,
+            max_entries: 500_000,
+            max_path_length: 4096,
+        }
+    }
+}
+
+/// What to do with packages whose content is unsafe to install (path traversal, device files, setuid binaries...)
+#[derive(Serialize, Deserialize, Copy, Clone, Eq, PartialEq, Hash, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum SafetyPolicy {
+    /// Refuse to publish them
+    Reject,
+    /// Publish them, but expose the issues found
+    Flag,
+}
+
+impl Default for SafetyPolicy {
+    fn default() -> Self {
+        SafetyPolicy::Reject
+    }
+}
+
+/// A key signing the index, as declared in the `[[signing_keys]]` section of the configuration.
+#[derive(Serialize, Deserialize, Clone, Eq, PartialEq, Hash, Debug)]
+pub struct SigningKeyConfig {
+    secret_key: PathBuf,
+    #[serde(default)]
+    expires: Option<DateTime<Utc>>,
+}
+
+impl SigningKeyConfig {
+    /// The file holding the hex-encoded 32-byte ed25519 secret key.
+    pub fn secret_key(&self) -> &Path {
+        &self.secret_key
+    }
+
+    /// When the key stops being used, at the end of a rotation.
+    pub fn expires(&self) -> Option<&DateTime<Utc>> {
+        self.expires.as_ref()
+    }
+}
+
+/// The public key of a maintainer, as declared in the `[[maintainer_keys]]` section of the configuration.
+///
+/// It can sign the packages of the given categories, and those whose `maintainer` is one of the given emails.
+#[derive(Serialize, Deserialize, Clone, Eq, PartialEq, Hash, Debug)]
+pub struct MaintainerKey {
+    name: String,
+    public_key: String,
+    #[serde(default)]
+    categories: Vec<CategoryName>,
+    #[serde(default)]
+    emails: Vec<String>,
+}
+
+impl MaintainerKey {
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// The hex-encoded ed25519 public key.
+    pub fn public_key(&self) -> &str {
+        &self.public_key
+    }
+
+    /// Test if the key can sign a package of the given category, maintained by the given email.
+    pub fn can_sign(&self, category: &CategoryName, maintainer: &str) -> bool {
+        self.categories.contains(category)
+            || self
+                .emails
+                .iter()
+                .any(|email| email.eq_ignore_ascii_case(maintainer.trim()))
+    }
+}
+
+/// The permissions that can be granted to an authentication token.
+#[derive(Serialize, Deserialize, Copy, Clone, Eq, PartialEq, Hash, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum Scope {
+    Upload,
+    Delete,
+    Admin,
+}
+
+/// A named authentication token, as declared in the `[[tokens]]` section of the configuration.
+#[derive(Serialize, Deserialize, Clone, Eq, PartialEq, Hash, Debug)]
+pub struct Token {
+    name: String,
+    hash: TokenHash,
+    scopes: Vec<Scope>,
+    #[serde(default)]
+    categories: Option<Vec<CategoryName>>,
+}
+
+impl Token {
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn hash(&self) -> &TokenHash {
+        &self.hash
+    }
+
+    pub fn scopes(&self) -> &[Scope] {
+        &self.scopes
+    }
+
+    pub fn categories(&self) -> Option<&[CategoryName]> {
+        self.categories.as_ref().map(Vec::as_slice)
+    }
+
+    /// Test if the token was granted the given scope.
+    ///
+    /// The `admin` scope implies all the others.
+    pub fn has_scope(&self, scope: Scope) -> bool {
+        self.scopes.contains(&scope) || self.scopes.contains(&Scope::Admin)
+    }
+
+    /// Test if the token can act on packages of the given category.
+    ///
+    /// Tokens without a list of categories can act on all of them.
+    pub fn can_access(&self, category: &CategoryName) -> bool {
+        self.categories
+            .as_ref()
+            .map_or(true, |categories| categories.contains(category))
+    }
+}
@@ -0,0 +1,38 @@
+use std::sync::{Arc, RwLock};
+
+use rocket::http::Status;
+use rocket::State;
+use rocket_contrib::templates::Template;
+use serde_json::json;
+
+use crate::api::auth::AuthToken;
+use crate::config::Config;
+use crate::package::NPFManager;
+
+#[get("/admin/dependencies")]
+pub fn dependencies(
+    config: State<Arc<Config>>,
+    npf_manager: State<Arc<RwLock<NPFManager>>>,
+    token: AuthToken,
+) -> Result<Template, Status> {
+    if !token.is_admin() {
+        return Err(Status::Forbidden);
+    }
+
+    let npf_manager = npf_manager
+        .read()
+        .expect("can't open the NPF manager in read-only mode");
+
+    let report = npf_manager.dependency_report();
+
+    Ok(Template::render(
+        "pages/admin/dependencies",
+        json!({
+            "name": config.name(),
+            "pretty_name": config.pretty_name(),
+            "links": config.links(),
+            "broken_len": report.broken().len(),
+            "report": report,
+        }),
+    ))
+}
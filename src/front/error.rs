@@ -1,62 +1,77 @@
 use std::sync::Arc;
 
+use rocket::http::Status;
 use rocket::Request;
 use rocket::State;
 use rocket_contrib::templates::Template;
 use serde_json::json;
 
+use crate::api::error::{is_api_request, ApiError};
 use crate::config::Config;
 
-#[catch(404)]
-pub fn not_found(request: &Request) -> Template {
+/// An error page, rendered as HTML for the website and as JSON for the API.
+#[derive(Responder)]
+pub enum ErrorPage {
+    Html(Template),
+    Json(ApiError),
+}
+
+fn render(request: &Request, status: Status, error: &str) -> ErrorPage {
+    if is_api_request(request) {
+        return ErrorPage::Json(ApiError::from_status(status));
+    }
+
     let config = request
         .guard::<State<Arc<Config>>>()
         .expect("can't retrieve the config state in error handler");
 
-    Template::render(
+    ErrorPage::Html(Template::render(
         "pages/error",
         json!({
             "name": config.name(),
             "pretty_name": config.pretty_name(),
             "links": config.links(),
-            "code": 404,
-            "error": "Page Not Found",
+            "code": status.code,
+            "error": error,
         }),
-    )
+    ))
+}
+
+#[catch(400)]
+pub fn bad_request(request: &Request) -> ErrorPage {
+    render(request, Status::BadRequest, "Bad Request")
 }
 
 #[catch(403)]
-pub fn forbidden(request: &Request) -> Template {
-    let config = request
-        .guard::<State<Arc<Config>>>()
-        .expect("can't retrieve the config state in error handler");
+pub fn forbidden(request: &Request) -> ErrorPage {
+    render(request, Status::Forbidden, "Forbidden")
+}
 
-    Template::render(
-        "pages/error",
-        json!({
-            "name": config.name(),
-            "pretty_name": config.pretty_name(),
-            "links": config.links(),
-            "code": 403,
-            "error": "Forbidden",
-        }),
-    )
+#[catch(404)]
+pub fn not_found(request: &Request) -> ErrorPage {
+    render(request, Status::NotFound, "Page Not Found")
 }
 
-#[catch(500)]
-pub fn internal_error(request: &Request) -> Template {
-    let config = request
-        .guard::<State<Config>>()
-        .expect("can't retrieve the config state in error handler");
+#[catch(409)]
+pub fn conflict(request: &Request) -> ErrorPage {
+    render(request, Status::Conflict, "Conflict")
+}
 
-    Template::render(
-        "pages/error",
-        json!({
-            "name": config.name(),
-            "pretty_name": config.pretty_name(),
-            "links": config.links(),
-            "code": 500,
-            "error": "Internal Server Error",
-        }),
+#[catch(413)]
+pub fn payload_too_large(request: &Request) -> ErrorPage {
+    render(request, Status::PayloadTooLarge, "Payload Too Large")
+}
+
+#[catch(422)]
+pub fn unprocessable_entity(request: &Request) -> ErrorPage {
+    render(request, Status::UnprocessableEntity, "Unprocessable Entity")
+}
+
+#[catch(500)]
+pub fn internal_error(request: &Request) -> ErrorPage {
+    render(
+        request,
+        Status::InternalServerError,
+        "Internal Server Error",
     )
 }
@@ -121,6 +121,22 @@ pub fn capitalize(
     Ok(())
 }
 
+pub fn octal(
+    h: &Helper,
+    _: &Handlebars,
+    _: &Context,
+    _: &mut RenderContext,
+    out: &mut dyn Output,
+) -> HelperResult {
+    let maybe_param = h.param(0).and_then(|param| param.value().as_u64());
+
+    if let Some(n) = maybe_param {
+        out.write(&format!("{:o}", n))?;
+    }
+
+    Ok(())
+}
+
 pub fn plural(
     h: &Helper,
     _: &Handlebars,
@@ -197,3 +213,28 @@ pub fn neq(
 
     Ok(())
 }
+
+pub fn urlencode(
+    h: &Helper,
+    _: &Handlebars,
+    _: &Context,
+    _: &mut RenderContext,
+    out: &mut dyn Output,
+) -> HelperResult {
+    if let Some(param) = h.param(0) {
+        let s = param.value().render();
+        let mut encoded = String::with_capacity(s.len());
+
+        for b in s.bytes() {
+            match b {
+                b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+                    encoded.push(b as char)
+                }
+                _ => encoded.push_str(&format!("%{:02X}", b)),
+            }
+        }
+        out.write(&encoded)?;
+    }
+
+    Ok(())
+}
@@ -28,7 +28,9 @@ pub fn metadata(
             .versions()
             .clone()
             .into_iter()
-            .map(|(version, metadata)| PackageVersion { version, metadata })
+            .map(|(version, metadata)| {
+                PackageVersion::from(&npf_manager, &manifest.full_name(), version, metadata)
+            })
             .collect::<Vec<_>>();
 
         // Sort by versions
@@ -43,6 +45,7 @@ pub fn metadata(
                 "sorted_versions": versions,
                 "last_version": versions.get(0),
                 "manifest": manifest,
+                "required_by": npf_manager.reverse_dependencies_of(&short_name),
             }),
         ))
     } else {
@@ -2,12 +2,41 @@ pub mod content;
 pub mod metadata;
 pub mod versions;
 
-use libnest::package::VersionData;
+use libnest::package::{PackageFullName, PackageID, VersionData};
 use semver::Version;
 use serde::{Deserialize, Serialize};
 
+use crate::package::safety::SafetyFinding;
+use crate::package::signature::Signer;
+use crate::package::NPFManager;
+
 #[derive(Serialize, Deserialize, Clone, Eq, PartialEq, Debug)]
 struct PackageVersion {
     pub version: Version,
     pub metadata: VersionData,
+    pub findings: Vec<SafetyFinding>,
+    pub signer: Option<Signer>,
+}
+
+impl PackageVersion {
+    fn from(
+        npf_manager: &NPFManager,
+        full_name: &PackageFullName,
+        version: Version,
+        metadata: VersionData,
+    ) -> Self {
+        let id = PackageID::from_full_name(full_name.clone(), version.clone());
+        let findings = npf_manager.safety_of(&id).unwrap_or_default();
+        let signer = npf_manager
+            .signer_of(&id)
+            .ok()
+            .and_then(|signer| signer.cloned());
+
+        Self {
+            version,
+            metadata,
+            findings,
+            signer,
+        }
+    }
 }
@@ -28,7 +28,9 @@ pub fn versions(
             .versions()
             .clone()
             .into_iter()
-            .map(|(version, metadata)| PackageVersion { version, metadata })
+            .map(|(version, metadata)| {
+                PackageVersion::from(&npf_manager, &manifest.full_name(), version, metadata)
+            })
             .collect::<Vec<_>>();
 
         // Sort by versions
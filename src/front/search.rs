@@ -5,30 +5,54 @@ use rocket_contrib::templates::Template;
 use serde_json::json;
 
 use crate::api;
+use crate::api::page::{self, Page, SortKey};
 use crate::config::Config;
+use crate::package::fulltext::SearchHit;
 use crate::package::NPFManager;
+use crate::param::SortParam;
 
-#[get("/search?<q>&search_by=content")]
+/// The number of results displayed on each page
+static RESULTS_PER_PAGE: usize = 50;
+
+/// The orders proposed to sort the results
+static SORTS: [&str; 4] = ["relevance", "name", "category", "date"];
+
+fn sort_name(sort: SortKey) -> &'static str {
+    match sort {
+        SortKey::Name => "name",
+        SortKey::Category => "category",
+        SortKey::Date => "date",
+        SortKey::Relevance => "relevance",
+    }
+}
+
+#[get("/search?<q>&<offset>&<sort>&search_by=content")]
 pub fn search_content(
     config: State<Arc<Config>>,
     npf_manager: State<Arc<RwLock<NPFManager>>>,
     q: String,
+    offset: Option<usize>,
+    sort: Option<SortParam>,
 ) -> Template {
     let npf_manager = npf_manager
         .read()
         .expect("can't open the NPF manager in read-only mode");
 
+    let sort = sort.map_or(SortKey::Relevance, Into::into);
     // Quick & dirty fix to avoid huge results if the query is empty.
     // FIXME
-    let results = {
+    let (mut results, truncated) = {
         if q != "" {
             npf_manager
                 .browse_packages_for_file(&q, false)
                 .unwrap_or_default()
         } else {
-            Vec::new()
+            (Vec::new(), false)
         }
     };
+    page::sort_content_results(&mut results, sort, &q, &npf_manager);
+
+    let page = Page::paginate(results, offset, Some(RESULTS_PER_PAGE)).truncated(truncated);
 
     Template::render(
         "pages/search/content",
@@ -38,23 +62,47 @@ pub fn search_content(
             "links": config.links(),
             "query": q,
             "search_by": "content",
-            "results_len": results.len(),
-            "results": results,
+            "sort": sort_name(sort),
+            "results_len": page.total(),
+            "truncated": page.is_truncated(),
+            "results": page.items(),
+            "first_result": page.offset() + 1,
+            "last_result": page.offset() + page.items().len(),
+            "sorts": SORTS,
+            "has_prev": page.prev_offset().is_some(),
+            "prev_offset": page.prev_offset(),
+            "has_next": page.next_offset().is_some(),
+            "next_offset": page.next_offset(),
         }),
     )
 }
 
-#[get("/search?<q>&<search_by>")]
+#[get("/search?<q>&<search_by>&<offset>&<sort>")]
 pub fn search(
     config: State<Arc<Config>>,
     npf_manager: State<Arc<RwLock<NPFManager>>>,
     q: String,
     search_by: String,
+    offset: Option<usize>,
+    sort: Option<SortParam>,
 ) -> Template {
     let npf_manager = npf_manager
         .read()
         .expect("can't open the NPF manager in read-only mode");
-    let results = api::search::do_search_metadata(&npf_manager, &q, &search_by, false);
+
+    let sort = sort.map_or(SortKey::Relevance, Into::into);
+    let results = if search_by == "all" {
+        // Full-text hits are already sorted by relevance
+        let mut hits = npf_manager.search_fulltext(&q);
+        page::sort_by_manifest(&mut hits, sort, |hit| hit.manifest());
+        hits.iter().map(SearchHit::manifest).collect()
+    } else {
+        let mut results = api::search::do_search_metadata(&npf_manager, &q, &search_by, false);
+        page::sort_manifests(&mut results, sort, &q);
+        results
+    };
+
+    let page = Page::paginate(results, offset, Some(RESULTS_PER_PAGE));
 
     Template::render(
         "pages/search/metadata",
@@ -64,8 +112,16 @@ pub fn search(
             "links": config.links(),
             "query": q,
             "search_by": search_by,
-            "results_len": results.len(),
-            "results": results,
+            "sort": sort_name(sort),
+            "results_len": page.total(),
+            "results": page.items(),
+            "first_result": page.offset() + 1,
+            "last_result": page.offset() + page.items().len(),
+            "sorts": SORTS,
+            "has_prev": page.prev_offset().is_some(),
+            "prev_offset": page.prev_offset(),
+            "has_next": page.next_offset().is_some(),
+            "next_offset": page.next_offset(),
         }),
     )
 }
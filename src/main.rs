@@ -5,11 +5,14 @@
 extern crate rocket;
 
 mod api;
+mod audit;
 mod config;
 mod front;
 mod package;
 mod param;
 
+use std::env;
+use std::process;
 use std::sync::{Arc, RwLock};
 
 use failure::Error;
@@ -17,19 +20,57 @@ use rocket_contrib::serve::StaticFiles;
 use rocket_contrib::templates::Template;
 use rocket_cors::AllowedOrigins;
 
+use crate::audit::AuditLog;
 use crate::config::Config;
 use crate::package::notify;
-use crate::package::NPFManager;
+use crate::package::signing::KeyRing;
+use crate::package::{self as npf, NPFManager};
 
 fn main() {
+    let args = env::args().skip(1).collect::<Vec<_>>();
+
+    // Helper mode generating the digest of a token, to be pasted in the configuration
+    if args.get(0).map(String::as_str) == Some("hash-token") {
+        match api::auth::hash_token_command(&args[1..]) {
+            Ok(hash) => println!("{}", hash),
+            Err(e) => {
+                eprintln!("Error: {}", e);
+                process::exit(1);
+            }
+        }
+        return;
+    }
+
+    // Helper mode generating a key to sign the index, whose path is to be pasted in the configuration
+    if args.get(0).map(String::as_str) == Some("gen-signing-key") {
+        match npf::signing::gen_signing_key_command(&args[1..]) {
+            Ok(public_key) => println!("{}", public_key),
+            Err(e) => {
+                eprintln!("Error: {}", e);
+                process::exit(1);
+            }
+        }
+        return;
+    }
+
     let res: Result<_, Error> = try {
         // Load the configuration
         let config = Arc::new(Config::load()?);
 
+        // Remove the leftovers of a previous instance
+        npf::clean_tmp_dirs(&config)?;
+
+        // Open the audit log
+        let audit_log = Arc::new(AuditLog::open(&config)?);
+
+        // Load the keys signing the index
+        let key_ring = Arc::new(KeyRing::load(&config)?);
+
         // Create the NPF cache manager
-        let mut npf_manager = NPFManager::new(config.clone());
+        let mut npf_manager = NPFManager::new(config.clone(), key_ring.clone());
         npf_manager.resync()?;
         let npf_manager = Arc::new(RwLock::new(npf_manager));
+        npf::refresh_snapshot(&npf_manager);
 
         // Asynchronously look for modifications on the file system
         notify::async_watch_fs(config.clone(), npf_manager.clone());
@@ -49,23 +90,38 @@ fn main() {
                     front::package::content::content,
                     front::package::metadata::metadata,
                     front::package::versions::versions,
+                    front::admin::dependencies,
                     api::home::home,
+                    api::audit::audit,
+                    api::report::dependencies,
                     api::pull::pull,
+                    api::signing::pull_sig,
+                    api::signing::key,
+                    api::graph::graph,
+                    api::resolve::resolve,
                     api::upload::upload,
                     api::search::search_metadata,
                     api::search::search_content,
                     api::package::content::content,
                     api::package::metadata::metadata,
+                    api::package::rdeps::rdeps,
                     api::package::version::version,
                     api::package::delete::delete,
                     api::package::download::download,
                     api::package::hash::hash,
+                    api::package::safety::safety,
+                    api::package::signer::signer,
+                    api::package::graph::graph,
                 ],
             )
             .register(catchers![
-                front::error::internal_error,
+                front::error::bad_request,
                 front::error::forbidden,
                 front::error::not_found,
+                front::error::conflict,
+                front::error::payload_too_large,
+                front::error::unprocessable_entity,
+                front::error::internal_error,
             ])
             .mount("/css", StaticFiles::from("front/static/css"))
             .mount("/js", StaticFiles::from("front/static/js"))
@@ -96,18 +152,27 @@ fn main() {
                 engines
                     .handlebars
                     .register_helper("plural", Box::new(front::hb::plural));
+                engines
+                    .handlebars
+                    .register_helper("octal", Box::new(front::hb::octal));
                 engines
                     .handlebars
                     .register_helper("eq", Box::new(front::hb::eq));
                 engines
                     .handlebars
                     .register_helper("neq", Box::new(front::hb::neq));
+                engines
+                    .handlebars
+                    .register_helper("urlencode", Box::new(front::hb::urlencode));
             }))
             .manage(config)
+            .manage(audit_log)
             .manage(npf_manager)
+            .manage(key_ring)
             .launch();
     };
     if let Err(e) = res {
-        println!("Error: {}", e);
+        eprintln!("Error: {}", e);
+        process::exit(1);
     }
 }
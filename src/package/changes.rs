@@ -0,0 +1,223 @@
+use std::collections::HashMap;
+use std::fmt::{self, Display, Formatter};
+use std::str::FromStr;
+
+use chrono::Utc;
+use failure::{format_err, Error};
+use libnest::package::{CategoryName, PackageManifest, PackageName, PackageShortName};
+use serde::{Serialize, Serializer};
+use serde_json::Value;
+
+/// A revision of the index, written `<epoch>.<number>`.
+///
+/// The number is increased each time a package is added, updated or removed. The epoch changes each time
+/// the server starts or forgets the changes of the index, so that a revision handed out by a previous instance
+/// is never mistaken for one of the current instance.
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug)]
+pub struct IndexRevision {
+    epoch: u64,
+    number: u64,
+}
+
+impl Display for IndexRevision {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "{}.{}", self.epoch, self.number)
+    }
+}
+
+impl FromStr for IndexRevision {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parts = s.splitn(2, '.');
+        let revision: Option<IndexRevision> = try {
+            IndexRevision {
+                epoch: parts.next()?.parse().ok()?,
+                number: parts.next()?.parse().ok()?,
+            }
+        };
+
+        revision.ok_or_else(|| format_err!("{}: invalid revision", s))
+    }
+}
+
+impl Serialize for IndexRevision {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+/// A package that doesn't exist anymore
+#[derive(Serialize, Clone, Eq, PartialEq, Hash, Debug)]
+pub struct RemovedPackage {
+    category: CategoryName,
+    name: PackageName,
+}
+
+/// The revisions at which a package was last created and modified
+#[derive(Clone, Debug)]
+struct PackageChange {
+    created: u64,
+    modified: u64,
+    removed: Option<RemovedPackage>,
+}
+
+/// The packages added, updated and removed since a given revision
+#[derive(Clone, Debug, Default)]
+struct ChangeSet {
+    added: Vec<PackageShortName>,
+    updated: Vec<PackageShortName>,
+    removed: Vec<RemovedPackage>,
+}
+
+/// The changes of the index since a given revision, as sent to clients.
+///
+/// If `full` is set, the changes since that revision aren't known: `added` contains all the packages,
+/// and those the client knows about but aren't listed were removed.
+#[derive(Serialize, Clone, Debug)]
+pub struct IndexChanges {
+    revision: IndexRevision,
+    full: bool,
+    added: Vec<Value>,
+    updated: Vec<Value>,
+    removed: Vec<RemovedPackage>,
+}
+
+/// Keeps track of the revision of the index, and of the revision at which each package last changed.
+///
+/// The changes are recorded by revision number, within the current epoch.
+/// The epoch is the current timestamp (in milliseconds), so that it keeps increasing when the server restarts.
+#[derive(Clone, Debug)]
+pub struct ChangeLog {
+    epoch: u64,
+    revision: u64,
+    packages: HashMap<PackageShortName, PackageChange>,
+}
+
+impl ChangeLog {
+    pub fn new() -> Self {
+        Self {
+            epoch: Utc::now().timestamp_millis().max(0) as u64,
+            revision: 0,
+            packages: HashMap::new(),
+        }
+    }
+
+    pub fn revision(&self) -> IndexRevision {
+        IndexRevision {
+            epoch: self.epoch,
+            number: self.revision,
+        }
+    }
+
+    /// Forget all changes and start a new epoch. Changes since any previous revision can't be computed anymore.
+    pub fn clear(&mut self) {
+        self.epoch = (Utc::now().timestamp_millis().max(0) as u64).max(self.epoch + 1);
+        self.revision = 0;
+        self.packages.clear();
+    }
+
+    /// Record that a package was added or updated
+    pub fn record_update(&mut self, name: PackageShortName) {
+        self.revision += 1;
+
+        let revision = self.revision;
+        let change = self.packages.entry(name).or_insert(PackageChange {
+            created: revision,
+            modified: revision,
+            removed: None,
+        });
+
+        if change.removed.take().is_some() {
+            change.created = revision;
+        }
+        change.modified = revision;
+    }
+
+    /// Record that a package was removed
+    pub fn record_removal(
+        &mut self,
+        name: PackageShortName,
+        category: CategoryName,
+        package: PackageName,
+    ) {
+        self.revision += 1;
+
+        let revision = self.revision;
+        let change = self.packages.entry(name).or_insert(PackageChange {
+            created: revision,
+            modified: revision,
+            removed: None,
+        });
+
+        change.modified = revision;
+        change.removed = Some(RemovedPackage {
+            category,
+            name: package,
+        });
+    }
+
+    /// Find the packages that changed since the given revision, along with their manifests serialized by the given function.
+    ///
+    /// If the changes since that revision aren't known, or no valid revision is given, the whole index is returned.
+    pub fn changes_since<F>(
+        &self,
+        since: Option<IndexRevision>,
+        manifests: &HashMap<PackageShortName, PackageManifest>,
+        entry: F,
+    ) -> IndexChanges
+    where
+        F: Fn(&PackageManifest) -> Value,
+    {
+        let manifests_of = |names: Vec<PackageShortName>| {
+            names
+                .iter()
+                .filter_map(|name| manifests.get(name))
+                .map(|manifest| entry(manifest))
+                .collect::<Vec<_>>()
+        };
+
+        match self.change_set(since) {
+            Some(changes) => IndexChanges {
+                revision: self.revision(),
+                full: false,
+                added: manifests_of(changes.added),
+                updated: manifests_of(changes.updated),
+                removed: changes.removed,
+            },
+            None => IndexChanges {
+                revision: self.revision(),
+                full: true,
+                added: manifests.values().map(|manifest| entry(manifest)).collect(),
+                updated: Vec::new(),
+                removed: Vec::new(),
+            },
+        }
+    }
+
+    /// Find the packages that changed since the given revision, or `None` if they aren't known
+    fn change_set(&self, since: Option<IndexRevision>) -> Option<ChangeSet> {
+        let since = match since {
+            Some(since) if since.epoch == self.epoch && since.number <= self.revision => {
+                since.number
+            }
+            _ => return None,
+        };
+
+        let mut changes = ChangeSet::default();
+        for (name, change) in &self.packages {
+            if change.modified <= since {
+                continue;
+            }
+
+            match &change.removed {
+                // Packages both created and removed since then were never seen by the client
+                Some(_) if change.created > since => (),
+                Some(removed) => changes.removed.push(removed.clone()),
+                None if change.created > since => changes.added.push(name.clone()),
+                None => changes.updated.push(name.clone()),
+            }
+        }
+        Some(changes)
+    }
+}
@@ -0,0 +1,142 @@
+use std::collections::HashMap;
+
+use libnest::package::{
+    PackageID, PackageManifest, PackageRequirement, PackageShortName, RepositoryName,
+};
+use serde::{Deserialize, Serialize};
+
+/// The reasons a requirement can't be satisfied
+#[derive(Serialize, Deserialize, Copy, Clone, Eq, PartialEq, Hash, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum DependencyIssue {
+    /// The required package doesn't exist in this repository
+    MissingPackage,
+    /// The required package exists, but none of its versions matches the requirement
+    NoMatchingVersion,
+    /// The requirement targets a repository that isn't listed in `foreign_repositories`
+    UnknownRepository,
+}
+
+/// A requirement of a version of a package that can't be satisfied
+#[derive(Serialize, Clone, Debug)]
+pub struct BrokenDependency {
+    package: PackageID,
+    requirement: PackageRequirement,
+    issue: DependencyIssue,
+    message: String,
+}
+
+/// The result of a check of all the dependencies of the repository
+#[derive(Serialize, Clone, Debug)]
+pub struct DependencyReport {
+    checked_packages: usize,
+    checked_requirements: usize,
+    broken: Vec<BrokenDependency>,
+}
+
+impl DependencyReport {
+    pub fn broken(&self) -> &[BrokenDependency] {
+        &self.broken
+    }
+}
+
+/// Check if a requirement can be satisfied, returning why it can't otherwise.
+///
+/// Requirements targeting a foreign repository are only checked against `foreign_repositories`,
+/// as their packages aren't known by this server.
+pub fn check_requirement(
+    repository: &RepositoryName,
+    foreign_repositories: &[RepositoryName],
+    manifests: &HashMap<PackageShortName, PackageManifest>,
+    requirement: &PackageRequirement,
+) -> Option<(DependencyIssue, String)> {
+    match requirement.repository() {
+        Some(target) if target != repository => {
+            if foreign_repositories.contains(target) {
+                None
+            } else {
+                Some((
+                    DependencyIssue::UnknownRepository,
+                    format!("the repository \"{}\" isn't known by this server", target),
+                ))
+            }
+        }
+        _ => {
+            let short_name =
+                PackageShortName::from(requirement.category().clone(), requirement.name().clone());
+
+            match manifests.get(&short_name) {
+                None => Some((
+                    DependencyIssue::MissingPackage,
+                    format!(
+                        "{}/{} doesn't exist in this repository",
+                        requirement.category(),
+                        requirement.name()
+                    ),
+                )),
+                Some(manifest)
+                    if !manifest
+                        .versions()
+                        .keys()
+                        .any(|version| requirement.version_requirement().matches(version)) =>
+                {
+                    Some((
+                        DependencyIssue::NoMatchingVersion,
+                        format!(
+                            "no version of {}/{} matches \"{}\"",
+                            requirement.category(),
+                            requirement.name(),
+                            requirement.version_requirement()
+                        ),
+                    ))
+                }
+                Some(_) => None,
+            }
+        }
+    }
+}
+
+/// Walk through all the versions of all the packages, reporting the requirements that can't be satisfied.
+pub fn check(
+    repository: &RepositoryName,
+    foreign_repositories: &[RepositoryName],
+    manifests: &HashMap<PackageShortName, PackageManifest>,
+) -> DependencyReport {
+    let mut checked_packages = 0;
+    let mut checked_requirements = 0;
+    let mut broken = Vec::new();
+
+    for manifest in manifests.values() {
+        for (version, version_data) in manifest.versions() {
+            checked_packages += 1;
+
+            for requirement in version_data.dependencies() {
+                checked_requirements += 1;
+
+                if let Some((issue, message)) =
+                    check_requirement(repository, foreign_repositories, manifests, requirement)
+                {
+                    broken.push(BrokenDependency {
+                        package: PackageID::from(
+                            repository.clone(),
+                            manifest.category().clone(),
+                            manifest.name().clone(),
+                            version.clone(),
+                        ),
+                        requirement: requirement.clone(),
+                        issue,
+                        message,
+                    });
+                }
+            }
+        }
+    }
+
+    broken.sort_by_key(|broken| broken.package.to_string());
+
+    DependencyReport {
+        checked_packages,
+        checked_requirements,
+        broken,
+    }
+}
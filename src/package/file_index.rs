@@ -0,0 +1,388 @@
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::iter;
+use std::mem;
+use std::path::Path;
+
+use failure::{Error, Fail};
+use glob::{MatchOptions, Pattern};
+use libnest::package::PackageID;
+use regex::{Regex, RegexBuilder};
+
+/// The maximum length of a glob or regex query
+static MAX_PATTERN_LENGTH: usize = 256;
+
+/// The maximum size of a compiled regex, to prevent pathological patterns from exhausting the memory
+static REGEX_SIZE_LIMIT: usize = 1 << 20;
+
+/// The minimum length of a substring query, so that it can be looked up with trigrams instead of scanning all the paths
+static MIN_SUBSTRING_LENGTH: usize = 3;
+
+#[derive(Clone, Eq, PartialEq, Ord, PartialOrd, Hash, Debug, Fail)]
+#[fail(display = "{}: invalid query", _0)]
+pub struct InvalidQueryError(String);
+
+/// Where the query must be found within a file name or path
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug)]
+pub enum Position {
+    Exact,
+    Prefix,
+    Suffix,
+    Substring,
+}
+
+/// What a query is matched against
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug)]
+pub enum Target {
+    /// The last component of the path
+    Name,
+    /// The full, absolute path
+    Path,
+}
+
+/// A content search query
+#[derive(Clone, Debug)]
+pub enum FileQuery {
+    /// A plain string, looked for in the file name or in the full path
+    Text {
+        query: String,
+        target: Target,
+        position: Position,
+    },
+    /// A glob pattern, matched against the full path. `*` doesn't match `/`, but `**` does.
+    Glob(Pattern),
+    /// A regular expression, matched against the full path
+    Regex(Regex),
+}
+
+impl FileQuery {
+    pub fn text(query: &str, target: Target, position: Position) -> Result<Self, Error> {
+        // Names starting with the query are looked up as paths containing `/<query>`
+        let min_length = match (target, position) {
+            (_, Position::Substring) => MIN_SUBSTRING_LENGTH,
+            (Target::Name, Position::Prefix) => MIN_SUBSTRING_LENGTH - 1,
+            _ => 1,
+        };
+        if query.len() < min_length {
+            Err(InvalidQueryError(format!(
+                "the query must be at least {} characters long",
+                min_length
+            )))?;
+        }
+
+        Ok(FileQuery::Text {
+            query: query.to_string(),
+            target,
+            position,
+        })
+    }
+
+    pub fn glob(query: &str) -> Result<Self, Error> {
+        check_pattern_length(query)?;
+        Ok(FileQuery::Glob(Pattern::new(query).map_err(|e| {
+            InvalidQueryError(format!("{}: {}", query, e.msg))
+        })?))
+    }
+
+    pub fn regex(query: &str) -> Result<Self, Error> {
+        check_pattern_length(query)?;
+        let regex = RegexBuilder::new(query)
+            .size_limit(REGEX_SIZE_LIMIT)
+            .dfa_size_limit(REGEX_SIZE_LIMIT)
+            .build()
+            .map_err(|e| InvalidQueryError(format!("{}: {}", query, e)))?;
+        Ok(FileQuery::Regex(regex))
+    }
+}
+
+fn check_pattern_length(query: &str) -> Result<(), InvalidQueryError> {
+    if query.is_empty() {
+        Err(InvalidQueryError("the pattern is empty".to_string()))
+    } else if query.len() > MAX_PATTERN_LENGTH {
+        Err(InvalidQueryError(format!(
+            "the pattern is longer than {} characters",
+            MAX_PATTERN_LENGTH
+        )))
+    } else {
+        Ok(())
+    }
+}
+
+/// Return the part of a glob pattern before its first special character
+fn literal_prefix(pattern: &Pattern) -> &str {
+    let pattern = pattern.as_str();
+    let end = pattern
+        .find(|c| c == '*' || c == '?' || c == '[')
+        .unwrap_or_else(|| pattern.len());
+    &pattern[..end]
+}
+
+type Trigram = [u8; 3];
+
+fn trigrams(s: &str) -> impl Iterator<Item = Trigram> + '_ {
+    s.as_bytes().windows(3).map(|w| [w[0], w[1], w[2]])
+}
+
+fn file_name(path: &str) -> &str {
+    Path::new(path)
+        .file_name()
+        .and_then(|name| name.to_str())
+        .unwrap_or("")
+}
+
+fn reversed(s: &str) -> String {
+    s.chars().rev().collect()
+}
+
+/// Return all the strings of the given set starting with the given prefix
+fn with_prefix(set: &BTreeSet<String>, prefix: String) -> impl Iterator<Item = &String> {
+    set.range(prefix.clone()..)
+        .take_while(move |s| s.starts_with(&prefix))
+}
+
+/// An in-memory inverted index from the files of all packages to the packages shipping them.
+///
+/// Each distinct path is stored once and given an identifier, which is freed once no package ships the path anymore
+/// and reused for the next new path. Lookups by exact name or path are
+/// done with hash maps, prefix and suffix lookups with sorted sets of the paths and of their
+/// reversal, and substring lookups with trigrams, so that no query has to scan all the paths.
+///
+/// The index is maintained incrementally as packages are added to or removed from the cache.
+#[derive(Clone, Debug, Default)]
+pub struct FileIndex {
+    paths: Vec<String>,
+    ids: HashMap<String, usize>,
+    packages: Vec<HashSet<PackageID>>,
+    by_name: HashMap<String, HashSet<usize>>,
+    sorted: BTreeSet<String>,
+    reversed: BTreeSet<String>,
+    trigrams: HashMap<Trigram, HashSet<usize>>,
+    contents: HashMap<PackageID, Vec<usize>>,
+    free: Vec<usize>,
+}
+
+impl FileIndex {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn clear(&mut self) {
+        *self = Self::new();
+    }
+
+    /// Index the files of the given package, replacing any previous content it had
+    pub fn insert<'a, I: IntoIterator<Item = &'a Path>>(&mut self, id: &PackageID, files: I) {
+        self.remove(id);
+
+        let mut content = Vec::new();
+        for path in files {
+            let path = path.display().to_string();
+            let index = self.intern(&path);
+
+            // New paths are added to the lookup structures
+            if self.packages[index].is_empty() {
+                self.link(index);
+            }
+            if self.packages[index].insert(id.clone()) {
+                content.push(index);
+            }
+        }
+        self.contents.insert(id.clone(), content);
+    }
+
+    /// Remove all the files of the given package from the index
+    pub fn remove(&mut self, id: &PackageID) {
+        if let Some(content) = self.contents.remove(id) {
+            for index in content {
+                self.packages[index].remove(id);
+
+                // The set of packages shipping a path acts as its reference count
+                if self.packages[index].is_empty() {
+                    self.release(index);
+                }
+            }
+        }
+    }
+
+    fn intern(&mut self, path: &str) -> usize {
+        if let Some(index) = self.ids.get(path) {
+            return *index;
+        }
+
+        let index = match self.free.pop() {
+            Some(index) => {
+                self.paths[index] = path.to_string();
+                index
+            }
+            None => {
+                self.paths.push(path.to_string());
+                self.packages.push(HashSet::new());
+                self.paths.len() - 1
+            }
+        };
+        self.ids.insert(path.to_string(), index);
+        index
+    }
+
+    fn link(&mut self, index: usize) {
+        let path = &self.paths[index];
+
+        self.by_name
+            .entry(file_name(path).to_string())
+            .or_default()
+            .insert(index);
+        self.sorted.insert(path.clone());
+        self.reversed.insert(reversed(path));
+        for trigram in trigrams(path) {
+            self.trigrams.entry(trigram).or_default().insert(index);
+        }
+    }
+
+    /// Forget a path that isn't shipped by any package anymore, so that its identifier can be reused
+    fn release(&mut self, index: usize) {
+        let path = mem::replace(&mut self.paths[index], String::new());
+
+        if let Some(indexes) = self.by_name.get_mut(file_name(&path)) {
+            indexes.remove(&index);
+            if indexes.is_empty() {
+                self.by_name.remove(file_name(&path));
+            }
+        }
+        self.sorted.remove(&path);
+        self.reversed.remove(&reversed(&path));
+        for trigram in trigrams(&path) {
+            if let Some(indexes) = self.trigrams.get_mut(&trigram) {
+                indexes.remove(&index);
+                if indexes.is_empty() {
+                    self.trigrams.remove(&trigram);
+                }
+            }
+        }
+        self.ids.remove(&path);
+        self.free.push(index);
+    }
+
+    /// Return the identifiers of all the shipped paths containing the given string
+    fn with_substring(&self, s: String) -> Box<dyn Iterator<Item = usize> + '_> {
+        let mut candidates: Option<HashSet<usize>> = None;
+
+        // Intersect the paths containing each trigram of the query, starting with the rarest one
+        let mut sets = trigrams(&s)
+            .map(|trigram| self.trigrams.get(&trigram))
+            .collect::<Option<Vec<_>>>()
+            .unwrap_or_default();
+        sets.sort_by_key(|set| set.len());
+
+        if sets.is_empty() && s.len() >= 3 {
+            return Box::new(iter::empty());
+        }
+
+        for set in sets {
+            candidates = Some(match candidates {
+                None => set.clone(),
+                Some(candidates) => candidates.intersection(set).cloned().collect(),
+            });
+        }
+
+        match candidates {
+            Some(candidates) => Box::new(
+                candidates
+                    .into_iter()
+                    .filter(move |index| self.paths[*index].contains(&s)),
+            ),
+            // Queries too short to have a trigram are checked against all the shipped paths
+            None => Box::new(
+                self.sorted
+                    .iter()
+                    .filter(move |path| path.contains(&s))
+                    .map(move |path| self.ids[path]),
+            ),
+        }
+    }
+
+    /// Find the paths matching the given query, with the packages shipping them.
+    ///
+    /// The lookup stops after `limit` matches, in which case the result is flagged as truncated.
+    /// The paths are returned in lexicographical order.
+    pub fn search(
+        &self,
+        query: &FileQuery,
+        limit: usize,
+    ) -> (Vec<(&str, &HashSet<PackageID>)>, bool) {
+        let matches: Box<dyn Iterator<Item = usize> + '_> = match query {
+            FileQuery::Text {
+                query,
+                target,
+                position,
+            } => self.search_text(query, *target, *position),
+            // Only the paths starting with the literal part of the pattern can match it
+            FileQuery::Glob(pattern) => {
+                let options = MatchOptions {
+                    case_sensitive: true,
+                    require_literal_separator: true,
+                    require_literal_leading_dot: false,
+                };
+
+                Box::new(
+                    with_prefix(&self.sorted, literal_prefix(pattern).to_string())
+                        .filter(move |path| pattern.matches_with(path, &options))
+                        .map(move |path| self.ids[path]),
+                )
+            }
+            FileQuery::Regex(regex) => Box::new(
+                self.sorted
+                    .iter()
+                    .filter(move |path| regex.is_match(path))
+                    .map(move |path| self.ids[path]),
+            ),
+        };
+
+        let mut indexes = matches.take(limit + 1).collect::<Vec<_>>();
+        let truncated = indexes.len() > limit;
+        indexes.truncate(limit);
+
+        indexes.sort_by(|a, b| self.paths[*a].cmp(&self.paths[*b]));
+        let paths = indexes
+            .into_iter()
+            .map(|index| (self.paths[index].as_str(), &self.packages[index]))
+            .collect();
+        (paths, truncated)
+    }
+
+    fn search_text<'a>(
+        &'a self,
+        query: &'a str,
+        target: Target,
+        position: Position,
+    ) -> Box<dyn Iterator<Item = usize> + 'a> {
+        match (target, position) {
+            (Target::Name, Position::Exact) => Box::new(
+                self.by_name
+                    .get(query)
+                    .into_iter()
+                    .flat_map(|indexes| indexes.iter().cloned()),
+            ),
+            (Target::Path, Position::Exact) => Box::new(self.ids.get(query).into_iter().cloned()),
+            (Target::Path, Position::Prefix) => Box::new(
+                with_prefix(&self.sorted, query.to_string()).map(move |path| self.ids[path]),
+            ),
+            // A name ending with the query is a path ending with it, as long as it doesn't span several components
+            (_, Position::Suffix) => Box::new(
+                with_prefix(&self.reversed, reversed(query))
+                    .map(move |path| self.ids[&reversed(path)])
+                    .filter(move |index| {
+                        target == Target::Path || file_name(&self.paths[*index]).ends_with(query)
+                    }),
+            ),
+            // A name starting with the query is a path containing `/<query>`
+            (Target::Name, Position::Prefix) => Box::new(
+                self.with_substring(format!("/{}", query))
+                    .filter(move |index| file_name(&self.paths[*index]).starts_with(query)),
+            ),
+            (Target::Path, Position::Substring) => self.with_substring(query.to_string()),
+            (Target::Name, Position::Substring) => Box::new(
+                self.with_substring(query.to_string())
+                    .filter(move |index| file_name(&self.paths[*index]).contains(query)),
+            ),
+        }
+    }
+}
@@ -0,0 +1,118 @@
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+
+use data_encoding::HEXUPPER;
+use failure::Error;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use tar::{Entry, EntryType};
+
+/// The version of the format of `files.map`.
+///
+/// It must be bumped each time [`FileEntry`] changes, so that outdated cache entries are rebuilt.
+/// It is also written to `files.map.format`, which is what cache entries are checked against.
+pub const FILES_MAP_FORMAT: u32 = 2;
+
+/// The type of an entry of `data.tar.gz`
+#[derive(Serialize, Deserialize, Copy, Clone, Eq, PartialEq, Hash, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum FileType {
+    File,
+    Directory,
+    Symlink,
+    HardLink,
+    CharDevice,
+    BlockDevice,
+    Fifo,
+    Other,
+}
+
+impl From<EntryType> for FileType {
+    fn from(entry_type: EntryType) -> Self {
+        match entry_type {
+            EntryType::Regular | EntryType::Continuous => FileType::File,
+            EntryType::Directory => FileType::Directory,
+            EntryType::Symlink => FileType::Symlink,
+            EntryType::Link => FileType::HardLink,
+            EntryType::Char => FileType::CharDevice,
+            EntryType::Block => FileType::BlockDevice,
+            EntryType::Fifo => FileType::Fifo,
+            _ => FileType::Other,
+        }
+    }
+}
+
+/// A file contained in a package, as recorded in `files.map`
+#[derive(Serialize, Deserialize, Clone, Eq, PartialEq, Hash, Debug)]
+pub struct FileEntry {
+    path: PathBuf,
+    #[serde(rename = "type")]
+    file_type: FileType,
+    size: u64,
+    mode: u32,
+    uid: u64,
+    gid: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    link_target: Option<PathBuf>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    sha256: Option<String>,
+}
+
+impl FileEntry {
+    /// Build the record of an entry of `data.tar.gz`, stored under the given (beautified) path.
+    ///
+    /// Regular files are read entirely to compute their digest.
+    pub fn from_entry<R: Read>(path: PathBuf, entry: &mut Entry<R>) -> Result<Self, Error> {
+        let header = entry.header();
+        let file_type = FileType::from(header.entry_type());
+        let size = header.size()?;
+        let mode = header.mode()?;
+        let uid = header.uid()?;
+        let gid = header.gid()?;
+        let link_target = entry.link_name()?.map(|target| target.into_owned());
+
+        let sha256 = if file_type == FileType::File {
+            let mut hasher = Sha256::default();
+            io::copy(entry, &mut hasher)?;
+            Some(HEXUPPER.encode(hasher.result().as_ref()))
+        } else {
+            None
+        };
+
+        Ok(Self {
+            path,
+            file_type,
+            size,
+            mode,
+            uid,
+            gid,
+            link_target,
+            sha256,
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+/// The content of `files.map`
+#[derive(Serialize, Deserialize, Clone, Eq, PartialEq, Hash, Debug)]
+pub struct FilesMap {
+    #[serde(default)]
+    format: u32,
+    files: Vec<FileEntry>,
+}
+
+impl FilesMap {
+    pub fn new(files: Vec<FileEntry>) -> Self {
+        Self {
+            format: FILES_MAP_FORMAT,
+            files,
+        }
+    }
+
+    pub fn into_files(self) -> Vec<FileEntry> {
+        self.files
+    }
+}
@@ -0,0 +1,217 @@
+use std::collections::HashMap;
+
+use libnest::package::{PackageManifest, PackageShortName};
+use serde::Serialize;
+use serde_json::Value;
+
+/// The fields of a manifest looked at by a full-text search, with their weight.
+///
+/// A match in the name of a package is worth more than a match in its description.
+static FIELDS: [(&str, f64); 6] = [
+    ("name", 10.0),
+    ("category", 4.0),
+    ("tags", 5.0),
+    ("description", 2.0),
+    ("maintainer", 1.0),
+    ("upstream_url", 1.0),
+];
+
+/// The bonus given to packages whose name is exactly the query
+static EXACT_NAME_BONUS: f64 = 20.0;
+
+/// A package found by a full-text search, with its relevance score.
+///
+/// It is serialized as the manifest of the package, with an additional `score` field.
+#[derive(Serialize, Clone, Debug)]
+pub struct SearchHit<'a> {
+    score: f64,
+    #[serde(flatten)]
+    manifest: &'a PackageManifest,
+}
+
+impl<'a> SearchHit<'a> {
+    pub fn manifest(&self) -> &'a PackageManifest {
+        self.manifest
+    }
+}
+
+/// The words of each searchable field of a manifest, with the weight of the field
+#[derive(Clone, Debug)]
+struct Document {
+    name: String,
+    fields: Vec<(Vec<String>, f64)>,
+}
+
+impl Document {
+    fn from(manifest: &PackageManifest) -> Option<Self> {
+        let value = serde_json::to_value(manifest).ok()?;
+        Some(Self {
+            name: manifest.name().as_ref().to_lowercase(),
+            fields: FIELDS
+                .iter()
+                .map(|(field, weight)| (tokenize(&field_text(&value, field)), *weight))
+                .collect(),
+        })
+    }
+}
+
+/// The words of all the manifests of the repository, so that they aren't tokenized again on each search.
+#[derive(Clone, Debug, Default)]
+pub struct SearchIndex {
+    documents: HashMap<PackageShortName, Document>,
+}
+
+impl SearchIndex {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Index the given manifest, replacing its previous words if any
+    pub fn insert(&mut self, short_name: PackageShortName, manifest: &PackageManifest) {
+        match Document::from(manifest) {
+            Some(document) => {
+                self.documents.insert(short_name, document);
+            }
+            None => {
+                self.documents.remove(&short_name);
+            }
+        }
+    }
+
+    pub fn remove(&mut self, short_name: &PackageShortName) {
+        self.documents.remove(short_name);
+    }
+
+    pub fn clear(&mut self) {
+        self.documents.clear();
+    }
+
+    /// Search the given manifests for the query in all their fields, case-insensitively.
+    ///
+    /// Hits are returned from the most to the least relevant.
+    pub fn search<'a>(
+        &self,
+        manifests: &'a HashMap<PackageShortName, PackageManifest>,
+        query: &str,
+    ) -> Vec<SearchHit<'a>> {
+        let query = tokenize(query);
+        if query.is_empty() {
+            return Vec::new();
+        }
+
+        let mut hits = self
+            .documents
+            .iter()
+            .filter_map(|(short_name, document)| {
+                let manifest = manifests.get(short_name)?;
+                score(document, &query).map(|score| SearchHit { score, manifest })
+            })
+            .collect::<Vec<_>>();
+
+        hits.sort_by(|a, b| {
+            b.score
+                .partial_cmp(&a.score)
+                .unwrap_or(std::cmp::Ordering::Equal)
+                .then_with(|| a.manifest.name().cmp(b.manifest.name()))
+        });
+        hits
+    }
+}
+
+/// Split a text in lowercase alphanumeric words
+fn tokenize(text: &str) -> Vec<String> {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(str::to_lowercase)
+        .collect()
+}
+
+/// The Levenshtein distance between two words
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b = b.chars().collect::<Vec<_>>();
+    let mut prev = (0..=b.len()).collect::<Vec<_>>();
+    let mut cur = vec![0; b.len() + 1];
+
+    for (i, ca) in a.chars().enumerate() {
+        cur[0] = i + 1;
+        for (j, cb) in b.iter().enumerate() {
+            let cost = if ca == *cb { 0 } else { 1 };
+            cur[j + 1] = (prev[j] + cost).min(prev[j + 1] + 1).min(cur[j] + 1);
+        }
+        std::mem::swap(&mut prev, &mut cur);
+    }
+    prev[b.len()]
+}
+
+/// How well a word of the query matches a word of a field, between `0` (no match) and `1` (exact match).
+///
+/// Typos are tolerated: one edit in words of 4 characters or more, two in words of 8 characters or more.
+fn word_similarity(query: &str, word: &str) -> f64 {
+    if query == word {
+        1.0
+    } else if word.starts_with(query) {
+        0.8
+    } else if word.contains(query) {
+        0.6
+    } else {
+        let len = query.chars().count();
+        let max_distance = match len {
+            0..=3 => 0,
+            4..=7 => 1,
+            _ => 2,
+        };
+
+        match edit_distance(query, word) {
+            0 => 1.0,
+            d if d <= max_distance => 0.5 - 0.1 * d as f64,
+            _ => 0.0,
+        }
+    }
+}
+
+/// Extract the textual content of the given field of a manifest
+fn field_text(manifest: &Value, field: &str) -> String {
+    let value = manifest.get(field).or_else(|| {
+        manifest
+            .get("metadata")
+            .and_then(|metadata| metadata.get(field))
+    });
+
+    match value {
+        Some(Value::String(s)) => s.clone(),
+        Some(Value::Array(values)) => values
+            .iter()
+            .filter_map(Value::as_str)
+            .collect::<Vec<_>>()
+            .join(" "),
+        _ => String::new(),
+    }
+}
+
+/// Compute the relevance of a manifest for the given query words.
+///
+/// Every word of the query must match at least one field, otherwise the score is `None`.
+fn score(document: &Document, query: &[String]) -> Option<f64> {
+    let mut total = 0.0;
+    for query_word in query {
+        let best = document
+            .fields
+            .iter()
+            .flat_map(|(words, weight)| {
+                words
+                    .iter()
+                    .map(move |word| weight * word_similarity(query_word, word))
+            })
+            .fold(0.0, f64::max);
+
+        if best <= 0.0 {
+            return None;
+        }
+        total += best;
+    }
+
+    if document.name == query.join("-") {
+        total += EXACT_NAME_BONUS;
+    }
+    Some(total)
+}
@@ -0,0 +1,349 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap, VecDeque};
+use std::fmt::Write;
+
+use libnest::package::{
+    PackageID, PackageManifest, PackageRequirement, PackageShortName, RepositoryName,
+};
+use serde::Serialize;
+
+use crate::package::rdeps::ReverseDependencies;
+
+/// The edges followed when exploring the graph from a package
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug)]
+pub enum Direction {
+    /// The packages it depends on, transitively
+    Dependencies,
+    /// The packages depending on it, transitively
+    ReverseDependencies,
+}
+
+/// A node of the dependency graph.
+///
+/// It is either a version of a package of this repository, or a requirement that can't be resolved to one
+/// (because it targets another repository or because no version matches it).
+#[derive(Serialize, Clone, Eq, PartialEq, Hash, Debug)]
+pub struct Node {
+    id: String,
+    resolved: bool,
+}
+
+/// An edge of the dependency graph, from a package to one of its dependencies
+#[derive(Serialize, Clone, Eq, PartialEq, Ord, PartialOrd, Hash, Debug)]
+pub struct Edge {
+    from: String,
+    to: String,
+    requirement: String,
+}
+
+/// A dependency graph, with the cycles it contains
+#[derive(Serialize, Clone, Eq, PartialEq, Hash, Debug)]
+pub struct Graph {
+    nodes: Vec<Node>,
+    edges: Vec<Edge>,
+    cycles: Vec<Vec<String>>,
+}
+
+impl Graph {
+    /// Render the graph in the Graphviz DOT format.
+    ///
+    /// Unresolved requirements are dashed, and edges belonging to a cycle are red.
+    pub fn to_dot(&self, name: &str) -> String {
+        let quote = |s: &str| format!("\"{}\"", s.replace('\\', "\\\\").replace('"', "\\\""));
+        let in_cycle = self
+            .cycles
+            .iter()
+            .enumerate()
+            .flat_map(|(i, cycle)| cycle.iter().map(move |node| (node.as_str(), i)))
+            .collect::<HashMap<_, _>>();
+
+        let mut dot = String::new();
+        let _ = writeln!(dot, "digraph {} {{", quote(name));
+        for node in &self.nodes {
+            if node.resolved {
+                let _ = writeln!(dot, "    {};", quote(&node.id));
+            } else {
+                let _ = writeln!(dot, "    {} [style=dashed];", quote(&node.id));
+            }
+        }
+        for edge in &self.edges {
+            let cyclic = match (
+                in_cycle.get(edge.from.as_str()),
+                in_cycle.get(edge.to.as_str()),
+            ) {
+                (Some(a), Some(b)) => a == b,
+                _ => false,
+            };
+            let _ = writeln!(
+                dot,
+                "    {} -> {} [label={}{}];",
+                quote(&edge.from),
+                quote(&edge.to),
+                quote(&edge.requirement),
+                if cyclic { ", color=red" } else { "" }
+            );
+        }
+        dot.push_str("}\n");
+        dot
+    }
+}
+
+/// The state of Tarjan's algorithm, used to find the strongly connected components of the graph
+struct Tarjan<'a> {
+    successors: &'a HashMap<&'a str, Vec<&'a str>>,
+    index: HashMap<&'a str, usize>,
+    lowlink: HashMap<&'a str, usize>,
+    stack: Vec<&'a str>,
+    on_stack: BTreeSet<&'a str>,
+    components: Vec<Vec<String>>,
+}
+
+impl<'a> Tarjan<'a> {
+    fn visit(&mut self, node: &'a str) {
+        let successors = self.successors;
+        let index = self.index.len();
+        self.index.insert(node, index);
+        self.lowlink.insert(node, index);
+        self.stack.push(node);
+        self.on_stack.insert(node);
+
+        for &successor in successors.get(node).into_iter().flatten() {
+            if !self.index.contains_key(successor) {
+                self.visit(successor);
+                let lowlink = self.lowlink[node].min(self.lowlink[successor]);
+                self.lowlink.insert(node, lowlink);
+            } else if self.on_stack.contains(successor) {
+                let lowlink = self.lowlink[node].min(self.index[successor]);
+                self.lowlink.insert(node, lowlink);
+            }
+        }
+
+        if self.lowlink[node] == self.index[node] {
+            let mut component = Vec::new();
+            while let Some(member) = self.stack.pop() {
+                self.on_stack.remove(member);
+                component.push(member.to_string());
+                if member == node {
+                    break;
+                }
+            }
+            component.sort();
+            self.components.push(component);
+        }
+    }
+}
+
+/// Find the cycles of the graph made of the given edges.
+///
+/// Each cycle is given as the sorted list of the nodes it goes through.
+fn find_cycles(edges: &BTreeSet<Edge>) -> Vec<Vec<String>> {
+    let mut successors: HashMap<&str, Vec<&str>> = HashMap::new();
+    for edge in edges {
+        successors
+            .entry(edge.from.as_str())
+            .or_default()
+            .push(edge.to.as_str());
+    }
+
+    let mut tarjan = Tarjan {
+        successors: &successors,
+        index: HashMap::new(),
+        lowlink: HashMap::new(),
+        stack: Vec::new(),
+        on_stack: BTreeSet::new(),
+        components: Vec::new(),
+    };
+    let mut roots = successors.keys().cloned().collect::<Vec<_>>();
+    roots.sort();
+    for root in roots {
+        if !tarjan.index.contains_key(root) {
+            tarjan.visit(root);
+        }
+    }
+
+    let mut cycles = tarjan
+        .components
+        .into_iter()
+        .filter(|component| {
+            let node = component[0].as_str();
+            component.len() > 1
+                || successors
+                    .get(node)
+                    .map_or(false, |successors| successors.contains(&node))
+        })
+        .collect::<Vec<_>>();
+    cycles.sort();
+    cycles
+}
+
+/// Builds dependency graphs out of the manifests of the repository
+pub struct GraphBuilder<'a> {
+    repository: &'a RepositoryName,
+    manifests: &'a HashMap<PackageShortName, PackageManifest>,
+    rdeps: &'a ReverseDependencies,
+    nodes: BTreeMap<String, bool>,
+    edges: BTreeSet<Edge>,
+}
+
+impl<'a> GraphBuilder<'a> {
+    pub fn new(
+        repository: &'a RepositoryName,
+        manifests: &'a HashMap<PackageShortName, PackageManifest>,
+        rdeps: &'a ReverseDependencies,
+    ) -> Self {
+        Self {
+            repository,
+            manifests,
+            rdeps,
+            nodes: BTreeMap::new(),
+            edges: BTreeSet::new(),
+        }
+    }
+
+    /// Find the version of a package of this repository a requirement resolves to: the most recent one matching it
+    fn resolve(&self, requirement: &PackageRequirement) -> Option<PackageID> {
+        if requirement
+            .repository()
+            .as_ref()
+            .map_or(false, |r| r != self.repository)
+        {
+            return None;
+        }
+
+        let short_name =
+            PackageShortName::from(requirement.category().clone(), requirement.name().clone());
+        let manifest = self.manifests.get(&short_name)?;
+
+        manifest
+            .versions()
+            .keys()
+            .filter(|version| requirement.version_requirement().matches(version))
+            .max()
+            .map(|version| {
+                PackageID::from(
+                    self.repository.clone(),
+                    manifest.category().clone(),
+                    manifest.name().clone(),
+                    version.clone(),
+                )
+            })
+    }
+
+    /// Add an edge between two nodes, returning the ID of the dependency if it's a package of this repository
+    fn link(&mut self, from: &PackageID, requirement: &PackageRequirement) -> Option<PackageID> {
+        let target = self.resolve(requirement);
+        let to = match &target {
+            Some(id) => id.to_string(),
+            None => requirement.to_string(),
+        };
+
+        self.nodes.insert(from.to_string(), true);
+        self.nodes.insert(to.clone(), target.is_some());
+        self.edges.insert(Edge {
+            from: from.to_string(),
+            to,
+            requirement: requirement.to_string(),
+        });
+        target
+    }
+
+    /// The versions of packages of this repository that directly depend on the given one
+    fn dependents_of(&self, id: &PackageID) -> Vec<(PackageID, PackageRequirement)> {
+        let short_name = PackageShortName::from(id.category().clone(), id.name().clone());
+
+        self.rdeps
+            .of(&short_name)
+            .into_iter()
+            .filter(|rdep| rdep.matches(id.version()))
+            .map(|rdep| (rdep.id(self.repository), rdep.requirement().clone()))
+            .collect()
+    }
+
+    /// The requirements of the given version of a package of this repository
+    fn requirements_of(&self, id: &PackageID) -> Vec<PackageRequirement> {
+        let short_name = PackageShortName::from(id.category().clone(), id.name().clone());
+
+        self.manifests
+            .get(&short_name)
+            .and_then(|manifest| manifest.versions().get(id.version()))
+            .map(|version_data| version_data.dependencies().iter().cloned().collect())
+            .unwrap_or_default()
+    }
+
+    /// Add all the versions of all the packages of the repository, and their dependencies
+    pub fn add_all(&mut self) {
+        let manifests = self.manifests;
+
+        for manifest in manifests.values() {
+            for (version, version_data) in manifest.versions() {
+                let id = PackageID::from(
+                    self.repository.clone(),
+                    manifest.category().clone(),
+                    manifest.name().clone(),
+                    version.clone(),
+                );
+
+                self.nodes.insert(id.to_string(), true);
+                for requirement in version_data.dependencies() {
+                    self.link(&id, requirement);
+                }
+            }
+        }
+    }
+
+    /// Add the given package and the packages reachable from it in the given direction, up to the given depth
+    pub fn add_closure(&mut self, root: &PackageID, direction: Direction, depth: Option<usize>) {
+        let mut visited = BTreeSet::new();
+        let mut queue = VecDeque::new();
+
+        self.nodes.insert(root.to_string(), true);
+        visited.insert(root.to_string());
+        queue.push_back((root.clone(), 0));
+
+        while let Some((id, distance)) = queue.pop_front() {
+            if depth.map_or(false, |depth| distance >= depth) {
+                continue;
+            }
+
+            let next = match direction {
+                Direction::Dependencies => self
+                    .requirements_of(&id)
+                    .iter()
+                    .filter_map(|requirement| self.link(&id, requirement))
+                    .collect::<Vec<_>>(),
+                Direction::ReverseDependencies => self
+                    .dependents_of(&id)
+                    .into_iter()
+                    .map(|(dependent, requirement)| {
+                        self.nodes.insert(dependent.to_string(), true);
+                        self.edges.insert(Edge {
+                            from: dependent.to_string(),
+                            to: id.to_string(),
+                            requirement: requirement.to_string(),
+                        });
+                        dependent
+                    })
+                    .collect(),
+            };
+
+            for id in next {
+                if visited.insert(id.to_string()) {
+                    queue.push_back((id, distance + 1));
+                }
+            }
+        }
+    }
+
+    pub fn build(self) -> Graph {
+        let cycles = find_cycles(&self.edges);
+
+        Graph {
+            nodes: self
+                .nodes
+                .into_iter()
+                .map(|(id, resolved)| Node { id, resolved })
+                .collect(),
+            edges: self.edges.into_iter().collect(),
+            cycles,
+        }
+    }
+}
@@ -0,0 +1,76 @@
+use std::path::Path;
+
+use failure::Fail;
+
+use crate::config::Limits;
+
+/// Error returned when an NPF exceeds one of the configured [`Limits`]
+#[derive(Clone, Eq, PartialEq, Hash, Debug, Fail)]
+#[fail(display = "{} exceeds the maximum allowed ({})", limit, max)]
+pub struct LimitExceededError {
+    limit: &'static str,
+    max: u64,
+}
+
+impl LimitExceededError {
+    pub fn new(limit: &'static str, max: u64) -> Self {
+        Self { limit, max }
+    }
+
+    /// The name of the limit that was exceeded, as written in the configuration
+    pub fn limit(&self) -> &str {
+        self.limit
+    }
+
+    pub fn max(&self) -> u64 {
+        self.max
+    }
+}
+
+/// Keep track of the resources consumed while walking through the entries of `data.tar.gz`,
+/// failing as soon as one of the [`Limits`] is exceeded.
+///
+/// Sizes are those declared by the tar headers: the archive can't yield more data than that
+/// for a given entry, so a decompression bomb is caught before it is fully decompressed.
+pub struct ArchiveBudget<'a> {
+    limits: &'a Limits,
+    entries: u64,
+    size: u64,
+}
+
+impl<'a> ArchiveBudget<'a> {
+    pub fn new(limits: &'a Limits) -> Self {
+        Self {
+            limits,
+            entries: 0,
+            size: 0,
+        }
+    }
+
+    /// Account for an entry of the given path and size
+    pub fn consume(&mut self, path: &Path, size: u64) -> Result<(), LimitExceededError> {
+        self.entries += 1;
+        if self.entries > self.limits.max_entries() {
+            return Err(LimitExceededError::new(
+                "max_entries",
+                self.limits.max_entries(),
+            ));
+        }
+
+        if path.as_os_str().len() > self.limits.max_path_length() {
+            return Err(LimitExceededError::new(
+                "max_path_length",
+                self.limits.max_path_length() as u64,
+            ));
+        }
+
+        self.size = self.size.saturating_add(size);
+        if self.size > self.limits.max_decompressed_size() {
+            return Err(LimitExceededError::new(
+                "max_decompressed_size",
+                self.limits.max_decompressed_size(),
+            ));
+        }
+        Ok(())
+    }
+}
@@ -1,14 +1,29 @@
+pub mod changes;
+pub mod consistency;
+pub mod file_index;
+pub mod files;
+pub mod fulltext;
+pub mod graph;
 pub mod history;
+pub mod limits;
 pub mod notify;
-
-use std::collections::HashMap;
+pub mod rdeps;
+pub mod resolve;
+pub mod safety;
+pub mod signature;
+pub mod signing;
+pub mod snapshot;
+pub mod validation;
+
+use std::collections::{BTreeMap, HashMap};
 use std::ffi::OsStr;
 use std::fs::{self, File};
 use std::io::{self, Read};
 use std::iter;
 use std::path::{Component, Path, PathBuf};
-use std::sync::Arc;
+use std::sync::{Arc, RwLock};
 
+use data_encoding::HEXUPPER;
 use failure::{format_err, Error, Fail};
 use flate2::read::GzDecoder;
 use libnest::package::{
@@ -19,10 +34,24 @@ use rand::distributions::Alphanumeric;
 use rand::{thread_rng, Rng};
 use semver::Version;
 use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256, Sha512};
 use tar::Archive;
 
-use crate::config::Config;
+use crate::config::{Config, Limits};
+use crate::package::changes::{ChangeLog, IndexChanges, IndexRevision};
+use crate::package::consistency::{self, DependencyReport};
+use crate::package::file_index::{FileIndex, FileQuery, Position, Target};
+use crate::package::files::{FileEntry, FilesMap, FILES_MAP_FORMAT};
+use crate::package::fulltext::{SearchHit, SearchIndex};
+use crate::package::graph::{Direction, Graph, GraphBuilder};
 use crate::package::history::History;
+use crate::package::limits::ArchiveBudget;
+use crate::package::rdeps::{ReverseDependencies, ReverseDependency};
+use crate::package::safety::{self, SafetyFinding};
+use crate::package::signature::{self, Signer};
+use crate::package::signing::{KeyRing, SignedIndex};
+use crate::package::snapshot::{Encoding, IndexSnapshot, SnapshotSource};
 
 #[derive(Clone, Eq, PartialEq, Ord, PartialOrd, Hash, Debug, Fail)]
 #[fail(display = "{}: invalid NPF path or name", _0)]
@@ -32,6 +61,10 @@ pub struct ParseNPFPathError(String);
 #[fail(display = "{}: NPF content and path don't match", _0)]
 pub struct NPFPackageIDError(String);
 
+#[derive(Clone, Eq, PartialEq, Ord, PartialOrd, Hash, Debug, Fail)]
+#[fail(display = "{}: package not found", _0)]
+pub struct PackageNotFoundError(String);
+
 /// Result of a content search
 #[derive(Serialize, Deserialize, Clone, Eq, PartialEq, Hash, Debug)]
 pub struct ContentSearchResult {
@@ -40,6 +73,16 @@ pub struct ContentSearchResult {
     all_versions: bool,
 }
 
+impl ContentSearchResult {
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn name(&self) -> &PackageFullName {
+        &self.name
+    }
+}
+
 /// Manager of all NPF.
 ///
 /// This structure transparently caches the PackageManifest and list of files
@@ -48,14 +91,32 @@ pub struct NPFManager {
     config: Arc<Config>,
     manifests: HashMap<PackageShortName, PackageManifest>,
     history: History,
+    file_index: FileIndex,
+    fulltext: SearchIndex,
+    rdeps: ReverseDependencies,
+    changes: ChangeLog,
+    snapshot: Option<Arc<IndexSnapshot>>,
+    key_ring: Arc<KeyRing>,
+    hashes: HashMap<PackageID, String>,
+    signers: HashMap<PackageID, Signer>,
+    signed_index: Option<Arc<SignedIndex>>,
 }
 
 impl NPFManager {
-    pub fn new(config: Arc<Config>) -> Self {
+    pub fn new(config: Arc<Config>, key_ring: Arc<KeyRing>) -> Self {
         Self {
             config,
             manifests: HashMap::new(),
             history: History::new(),
+            file_index: FileIndex::new(),
+            fulltext: SearchIndex::new(),
+            rdeps: ReverseDependencies::new(),
+            changes: ChangeLog::new(),
+            snapshot: None,
+            key_ring,
+            hashes: HashMap::new(),
+            signers: HashMap::new(),
+            signed_index: None,
         }
     }
 
@@ -67,10 +128,161 @@ impl NPFManager {
         self.manifests.values()
     }
 
+    /// Search all the manifests for the given query, from the most to the least relevant
+    pub fn search_fulltext(&self, query: &str) -> Vec<SearchHit> {
+        self.fulltext.search(&self.manifests, query)
+    }
+
     pub fn history(&self) -> &History {
         &self.history
     }
 
+    /// The revision of the index, increased each time a package is added, updated or removed.
+    pub fn revision(&self) -> IndexRevision {
+        self.changes.revision()
+    }
+
+    /// The prebuilt snapshot of the whole index, if it could be built.
+    pub fn snapshot(&self) -> Option<Arc<IndexSnapshot>> {
+        self.snapshot.clone()
+    }
+
+    /// Return the SHA-256 of the NPF of the given package, in uppercase hexadecimal
+    pub fn sha256_of(&self, id: &PackageID) -> Result<&str, Error> {
+        self.hashes
+            .get(id)
+            .map(String::as_str)
+            .ok_or_else(|| PackageNotFoundError(id.to_string()).into())
+    }
+
+    /// Serialize the given manifest, adding to each of its versions the SHA-256 of its NPF.
+    pub fn index_entry_of(&self, manifest: &PackageManifest) -> Value {
+        let mut entry = json!(manifest);
+
+        if let Some(versions) = entry.get_mut("versions").and_then(Value::as_object_mut) {
+            for version in manifest.versions().keys() {
+                let id = PackageID::from_full_name(manifest.full_name(), version.clone());
+
+                if let (Some(data), Some(sha256)) = (
+                    versions
+                        .get_mut(&version.to_string())
+                        .and_then(Value::as_object_mut),
+                    self.hashes.get(&id),
+                ) {
+                    data.insert("sha256".to_string(), json!(sha256));
+                }
+            }
+        }
+        entry
+    }
+
+    /// Return the maintainer who signed the given package, if its NPF has a valid signature
+    pub fn signer_of(&self, id: &PackageID) -> Result<Option<&Signer>, Error> {
+        let cache_entry = NPFCacheEntry::from(&self.config, &id);
+        if cache_entry.exists() {
+            Ok(self.signers.get(id))
+        } else {
+            Err(PackageNotFoundError(id.to_string()).into())
+        }
+    }
+
+    /// The signature of the current snapshot of the index, if it's signed.
+    pub fn signed_index(&self) -> Option<Arc<SignedIndex>> {
+        self.signed_index.clone()
+    }
+
+    /// Test if the current snapshot of the index isn't signed by exactly the active keys, as happens once a rotated key
+    /// expires
+    pub fn is_signature_outdated(&self) -> bool {
+        let signed_by = self
+            .signed_index
+            .as_ref()
+            .map(|signed_index| signed_index.key_ids())
+            .unwrap_or_default();
+
+        let is_current = self.snapshot.as_ref().map_or(false, |snapshot| {
+            snapshot.revision() == self.changes.revision()
+        });
+
+        is_current && signed_by != self.key_ring.active_key_ids()
+    }
+
+    /// Sign the current snapshot of the index again, with the active keys.
+    ///
+    /// A snapshot that's already outdated is left as is, as it is about to be rebuilt and signed anyway.
+    pub fn resign_index(&mut self) -> Result<(), Error> {
+        if let Some(snapshot) = &self.snapshot {
+            if snapshot.revision() == self.changes.revision() {
+                let signed_index = SignedIndex::sign(
+                    &self.key_ring,
+                    self.config.name().as_ref(),
+                    snapshot.revision(),
+                    snapshot.encoded(Encoding::Identity),
+                    &self.npf_digests(),
+                )?;
+                self.signed_index = signed_index.map(Arc::new);
+            }
+        }
+        Ok(())
+    }
+
+    /// The SHA-256 of the NPF of each package, indexed by their `PackageID`
+    fn npf_digests(&self) -> BTreeMap<String, String> {
+        self.hashes
+            .iter()
+            .map(|(id, sha256)| (id.to_string(), sha256.clone()))
+            .collect()
+    }
+
+    /// Collect the content of the index to rebuild its snapshot, if it's outdated
+    fn snapshot_source(&self) -> Option<SnapshotSource> {
+        let revision = self.changes.revision();
+
+        if self.snapshot.as_ref().map(|s| s.revision()) == Some(revision) {
+            return None;
+        }
+
+        Some(SnapshotSource::collect(
+            revision,
+            self.config.name().as_ref(),
+            self.manifests.values(),
+            |manifest| self.index_entry_of(manifest),
+            self.npf_digests(),
+            self.key_ring.clone(),
+        ))
+    }
+
+    /// Install the snapshot built for the given revision, unless the index changed in the meantime
+    fn set_snapshot(
+        &mut self,
+        revision: IndexRevision,
+        built: Result<(IndexSnapshot, Option<SignedIndex>), Error>,
+    ) {
+        if self.changes.revision() != revision {
+            return;
+        }
+
+        match built {
+            Ok((snapshot, signed_index)) => {
+                self.snapshot = Some(Arc::new(snapshot));
+                self.signed_index = signed_index.map(Arc::new);
+            }
+            Err(e) => {
+                eprintln!("[CACHE] Failed to build the index snapshot: {}", e);
+                self.snapshot = None;
+                self.signed_index = None;
+            }
+        }
+    }
+
+    /// Find the manifests added, updated and removed since the given revision of the index, if it is known.
+    pub fn changes_since(&self, since: Option<IndexRevision>) -> IndexChanges {
+        self.changes
+            .changes_since(since, &self.manifests, |manifest| {
+                self.index_entry_of(manifest)
+            })
+    }
+
     /// Find the manifest with the given name.
     ///
     /// The search is cached to speed-up the results.
@@ -78,6 +290,71 @@ impl NPFManager {
         self.manifests.get(name)
     }
 
+    /// Find all the versions of packages depending on the package with the given name.
+    pub fn reverse_dependencies_of(&self, name: &PackageShortName) -> Vec<&ReverseDependency> {
+        self.rdeps.of(name)
+    }
+
+    /// Find the versions of packages whose requirements would become unsatisfiable if the given package was removed.
+    pub fn dependents_broken_by_removal_of(&self, id: &PackageID) -> Vec<&ReverseDependency> {
+        let short_name = PackageShortName::from(id.category().clone(), id.name().clone());
+        let remaining = self
+            .manifests
+            .get(&short_name)
+            .map(|manifest| {
+                manifest
+                    .versions()
+                    .keys()
+                    .filter(|version| *version != id.version())
+                    .collect::<Vec<_>>()
+            })
+            .unwrap_or_default();
+
+        self.rdeps
+            .of(&short_name)
+            .into_iter()
+            .filter(|rdep| {
+                rdep.matches(id.version()) && !remaining.iter().any(|version| rdep.matches(version))
+            })
+            .collect()
+    }
+
+    /// Build the dependency graph of all the packages of the repository.
+    pub fn graph(&self) -> Graph {
+        let mut builder = GraphBuilder::new(self.config.name(), &self.manifests, &self.rdeps);
+        builder.add_all();
+        builder.build()
+    }
+
+    /// Build the dependency graph of the given package, following the given direction up to the given depth.
+    ///
+    /// Returns `None` if the package doesn't exist.
+    pub fn graph_of(
+        &self,
+        id: &PackageID,
+        direction: Direction,
+        depth: Option<usize>,
+    ) -> Option<Graph> {
+        let short_name = PackageShortName::from(id.category().clone(), id.name().clone());
+        self.manifests
+            .get(&short_name)?
+            .versions()
+            .get(id.version())?;
+
+        let mut builder = GraphBuilder::new(self.config.name(), &self.manifests, &self.rdeps);
+        builder.add_closure(id, direction, depth);
+        Some(builder.build())
+    }
+
+    /// Check that the dependencies of all packages can be satisfied.
+    pub fn dependency_report(&self) -> DependencyReport {
+        consistency::check(
+            self.config.name(),
+            self.config.foreign_repositories(),
+            &self.manifests,
+        )
+    }
+
     /// Find the content of the package with the given name.
     ///
     /// # Note
@@ -85,50 +362,76 @@ impl NPFManager {
     /// If a package doesn't have a content (i.e. it is a virtual package), this function returns `Ok(None)`.
     ///
     /// The search is cached to speed-up the results.
-    pub fn content_of(&self, id: &PackageID) -> Result<Option<Vec<PathBuf>>, Error> {
+    pub fn content_of(&self, id: &PackageID) -> Result<Option<Vec<FileEntry>>, Error> {
         let cache_entry = NPFCacheEntry::from(&self.config, &id);
         if cache_entry.exists() {
             cache_entry.filesmap()
         } else {
-            Err(format_err!("{}: package not found", id))
+            Err(PackageNotFoundError(id.to_string()).into())
+        }
+    }
+
+    /// Return the safety issues found in the content of the given package
+    pub fn safety_of(&self, id: &PackageID) -> Result<Vec<SafetyFinding>, Error> {
+        let cache_entry = NPFCacheEntry::from(&self.config, &id);
+        if cache_entry.exists() {
+            cache_entry.safety()
+        } else {
+            Err(PackageNotFoundError(id.to_string()).into())
         }
     }
 
     /// Find the packages that contain the given file.
     ///
-    /// This search has a terrible complexity, it is the current worst-case of this cache algorithm.
+    /// If `exact_match` is set, the file name must be equal to `content`, otherwise it must contain it.
     pub fn browse_packages_for_file(
         &self,
         content: &str,
         exact_match: bool,
-    ) -> Result<Vec<ContentSearchResult>, Error> {
-        let mut results = Vec::new();
-
-        for manifest in self.manifests.values() {
-            let mut counters: HashMap<PathBuf, usize> = HashMap::new();
+    ) -> Result<(Vec<ContentSearchResult>, bool), Error> {
+        let position = if exact_match {
+            Position::Exact
+        } else {
+            Position::Substring
+        };
+        Ok(self.search_files(&FileQuery::text(content, Target::Name, position)?))
+    }
 
-            for version in manifest.versions().keys() {
-                let id = PackageID::from_full_name(manifest.full_name(), version.clone());
-                let entry = NPFCacheEntry::from(&self.config, &id);
+    /// Find the packages that contain files matching the given query, using the file index.
+    ///
+    /// At most `max_search_results` files are looked up. The returned flag tells if there were more matching files.
+    pub fn search_files(&self, query: &FileQuery) -> (Vec<ContentSearchResult>, bool) {
+        let mut results = Vec::new();
+        let (paths, truncated) = self
+            .file_index
+            .search(query, self.config.max_search_results());
+
+        for (path, ids) in paths {
+            // Count how many versions of each package contain the file
+            let mut counters: HashMap<PackageShortName, usize> = HashMap::new();
+            for id in ids {
+                *counters.entry(id.clone().into()).or_default() += 1;
+            }
 
-                for result in entry.contains(content, exact_match)? {
-                    let counter = counters.entry(result).or_default();
-                    *counter += 1;
+            for (short_name, counter) in counters {
+                if let Some(manifest) = self.manifests.get(&short_name) {
+                    results.push(ContentSearchResult {
+                        path: PathBuf::from(path),
+                        name: manifest.full_name(),
+                        all_versions: counter == manifest.versions().len(),
+                    });
                 }
             }
-
-            let mut tmp_res = counters
-                .into_iter()
-                .map(|(path, counter)| ContentSearchResult {
-                    path,
-                    name: manifest.full_name(),
-                    all_versions: counter == manifest.versions().len(),
-                })
-                .collect::<Vec<_>>();
-
-            results.append(&mut tmp_res);
         }
-        Ok(results)
+        (results, truncated)
+    }
+
+    /// Index the content of the given cache entry, so that it can be found by content searches
+    fn index_files(&mut self, id: &PackageID, cache_entry: &NPFCacheEntry) -> Result<(), Error> {
+        let files = cache_entry.filesmap()?.unwrap_or_default();
+        self.file_index
+            .insert(id, files.iter().map(FileEntry::path));
+        Ok(())
     }
 
     /// Parse the path of an NPF to retrieve the [`PackageID`] it represents.
@@ -234,13 +537,52 @@ impl NPFManager {
             ),
         );
 
+        // Update the reverse dependencies
+        self.rdeps.insert(
+            self.config.name(),
+            &manifest.id(self.config.name().clone()),
+            manifest.dependencies().iter(),
+        );
+
         // Update the HashMap entry
+        self.changes.record_update(short_name.clone());
+        self.fulltext.insert(short_name.clone(), &pkg_manifest);
         self.manifests.insert(short_name, pkg_manifest.clone());
 
         // Insert the update in the history
         self.history.add_manifest(pkg_manifest);
     }
 
+    /// Record the digest of an NPF, and the maintainer who signed it if it has a valid detached signature
+    ///
+    /// The digest is read from the cache entry, so the NPF is only read again if it is signed.
+    fn check_npf(
+        &mut self,
+        id: &PackageID,
+        npf: &Path,
+        manifest: &Manifest,
+        cache_entry: &NPFCacheEntry,
+    ) -> Result<(), Error> {
+        self.hashes.insert(id.clone(), cache_entry.sha256()?);
+        self.signers.remove(id);
+
+        if let Ok(sig) = fs::read_to_string(signature::signature_path(npf)) {
+            match signature::verify(
+                &self.config,
+                manifest.category(),
+                manifest.metadata().maintainer(),
+                &file_digest::<Sha512>(npf)?,
+                &sig,
+            ) {
+                Ok(signer) => {
+                    self.signers.insert(id.clone(), signer);
+                }
+                Err(e) => eprintln!("[CACHE] Ignoring the signature of {}: {}", id, e),
+            }
+        }
+        Ok(())
+    }
+
     /// Flush (force update) the cache of an existing manifest
     fn add(&mut self, npf: &Path) -> Result<(), Error> {
         let id = self.parse_npf_path(npf)?;
@@ -264,6 +606,8 @@ impl NPFManager {
             }
 
             self.add_manifest(short_name, &manifest);
+            self.index_files(&id, &cache_entry)?;
+            self.check_npf(&id, npf, &manifest, &cache_entry)?;
 
             println!("[CACHE] Cache updated for {}", id);
         };
@@ -285,11 +629,24 @@ impl NPFManager {
             cache_entry.purge()?;
         }
 
+        self.file_index.remove(&id);
+        self.rdeps.remove(&id);
+        self.hashes.remove(&id);
+        self.signers.remove(&id);
+
         if let Some(manifest) = self.manifests.get_mut(&short_name) {
             manifest.versions_mut().remove(id.version());
             if manifest.versions().len() == 0 {
                 self.history.remove_manifest(manifest);
+                self.changes.record_removal(
+                    short_name.clone(),
+                    manifest.category().clone(),
+                    manifest.name().clone(),
+                );
+                self.fulltext.remove(&short_name);
                 self.manifests.remove(&short_name);
+            } else {
+                self.changes.record_update(short_name.clone());
             }
         }
 
@@ -301,14 +658,19 @@ impl NPFManager {
     /// Flush the cache entry of an arbitrary NPF.
     ///
     /// If the file doesn't exist, the entry is removed from the cache.
-    /// Non NPF files are ignored for simplicity.
+    /// If it's the detached signature of an NPF, only the signature is checked again.
+    /// Other files are ignored for simplicity.
+    /// The snapshot of the index is left untouched, see [`refresh_snapshot`].
     pub fn flush<P: AsRef<Path>>(&mut self, npf: P) -> Result<(), Error> {
         let npf = npf.as_ref();
+        let extension = npf.extension().and_then(OsStr::to_str);
 
-        // Ignore non-NPF files
-        if npf.extension().and_then(OsStr::to_str) == Some("nest") {
+        // Ignore other files, and uploads that are still being staged
+        if (extension == Some("nest") || extension == Some("sig")) && !self.is_staging(npf) {
             let r: Result<_, Error> = {
-                if npf.exists() {
+                if extension == Some("sig") {
+                    self.recheck_signature(&npf.with_extension(""))
+                } else if npf.exists() {
                     self.add(npf)
                 } else {
                     self.remove(npf)
@@ -329,10 +691,35 @@ impl NPFManager {
         }
     }
 
+    /// Check the signature of a cached NPF again, as detached signatures are published after their NPF
+    fn recheck_signature(&mut self, npf: &Path) -> Result<(), Error> {
+        let id = self.parse_npf_path(npf)?;
+        let cache_entry = NPFCacheEntry::from(&self.config, &id);
+
+        // NPFs that aren't cached yet have their signature checked when they are
+        if npf.exists() && self.hashes.contains_key(&id) {
+            let manifest = cache_entry.manifest()?;
+            self.check_npf(&id, npf, &manifest, &cache_entry)?;
+            println!("[CACHE] Signature checked for {}", id);
+        }
+        Ok(())
+    }
+
+    /// Test if the given path is within the staging directory, where uploads are written before being published.
+    fn is_staging(&self, path: &Path) -> bool {
+        path.starts_with(self.config.staging_dir())
+    }
+
     /// Ensure the cache of all manifests isn't dirty, flush it otherwise.
     pub fn resync(&mut self) -> Result<(), Error> {
         self.manifests.clear();
         self.history.clear();
+        self.file_index.clear();
+        self.fulltext.clear();
+        self.rdeps.clear();
+        self.changes.clear();
+        self.hashes.clear();
+        self.signers.clear();
 
         for npf_path in glob::glob(&format!(
             "{}/*/*/*.nest",
@@ -340,15 +727,22 @@ impl NPFManager {
         ))? {
             let _: Result<(), Error> = try {
                 let npf_path = npf_path?;
+                if self.is_staging(&npf_path) {
+                    continue;
+                }
+
                 let id = self.parse_npf_path(&npf_path)?;
 
                 let cache_entry = NPFCacheEntry::from(&self.config, &id);
                 if !cache_entry.exists() || cache_entry.is_dirty()? {
                     println!("[CACHE] Cache dirty for {}, flushing.", id);
-                    self.flush(npf_path)?;
+                    self.flush(&npf_path)?;
                 } else {
                     println!("[CACHE] Cache is up to date for {}.", id);
-                    self.add_manifest(id.into(), &cache_entry.manifest()?);
+                    let manifest = cache_entry.manifest()?;
+                    self.add_manifest(id.clone().into(), &manifest);
+                    self.index_files(&id, &cache_entry)?;
+                    self.check_npf(&id, &npf_path, &manifest, &cache_entry)?;
                 }
             };
         }
@@ -357,11 +751,43 @@ impl NPFManager {
     }
 }
 
+/// Rebuild and sign the snapshot of the whole index, if it's outdated.
+///
+/// Compressing and signing the snapshot is slow, so the manager is only locked to copy the content of the index
+/// and to install the result.
+pub fn refresh_snapshot(npf_manager: &RwLock<NPFManager>) {
+    let source = npf_manager
+        .read()
+        .expect("can't open the NPF manager in read-only mode")
+        .snapshot_source();
+
+    if let Some(source) = source {
+        let revision = source.revision();
+        let built = source.build();
+
+        npf_manager
+            .write()
+            .expect("can't open the NPF manager in read-write mode")
+            .set_snapshot(revision, built);
+    }
+}
+
 pub struct NPFCacheEntry {
+    tmp_dir: PathBuf,
+    limits: Limits,
     cache_path: PathBuf,
     npf_path: PathBuf,
     manifest_path: PathBuf,
     filesmap_path: PathBuf,
+    filesmap_format_path: PathBuf,
+    safety_path: PathBuf,
+    digest_path: PathBuf,
+}
+
+/// The digests of an NPF, computed once when its cache entry is filled
+#[derive(Serialize, Deserialize, Clone, Eq, PartialEq, Hash, Debug)]
+struct NPFDigest {
+    sha256: String,
 }
 
 impl NPFCacheEntry {
@@ -379,9 +805,14 @@ impl NPFCacheEntry {
             .join(format!("{}-{}.nest", id.name(), id.version().to_string()));
 
         Self {
+            tmp_dir: config.tmp_dir().to_path_buf(),
+            limits: config.limits().clone(),
             npf_path,
             manifest_path: cache_path.join("manifest.toml"),
             filesmap_path: cache_path.join("files.map"),
+            filesmap_format_path: cache_path.join("files.map.format"),
+            safety_path: cache_path.join("safety.json"),
+            digest_path: cache_path.join("digest.json"),
             cache_path,
         }
     }
@@ -397,7 +828,19 @@ impl NPFCacheEntry {
     }
 
     /// Test if the cache entry is dirty
+    ///
+    /// Entries whose `files.map` was written with an older format, or filled before digests were cached,
+    /// are always dirty, so they are rebuilt transparently.
+    /// The format is stored in a file of its own, so that `files.map` doesn't have to be parsed to check it.
     pub fn is_dirty(&self) -> Result<bool, Error> {
+        if self.filesmap_path.exists() && self.filesmap_format() != Some(FILES_MAP_FORMAT) {
+            return Ok(true);
+        }
+
+        if self.manifest_path.exists() && !self.digest_path.exists() {
+            return Ok(true);
+        }
+
         if self.npf_path.exists() && self.manifest_path.exists() {
             let npf_modified = fs::metadata(&self.npf_path)?.modified()?;
             let manifest_modified = fs::metadata(&self.manifest_path)?.modified()?;
@@ -416,16 +859,17 @@ impl NPFCacheEntry {
     ///
     /// This function explores the NPF by extracting it in a temporary folder.
     pub fn fill_with<P: AsRef<Path>>(&mut self, npf_path: P) -> Result<(), Error> {
-        let npf_explorer = NPFExplorer::open_at(npf_path.as_ref(), "/var/tmp/nest-server")?;
+        let npf_explorer = NPFExplorer::open_at(npf_path.as_ref(), &self.tmp_dir)?;
         let manifest = npf_explorer.manifest();
 
-        // If this is a reupload of an already existing package, remove the previous content
+        // If this is a (forced) reupload of an already existing package, remove the previous content
         if self.exists() {
             self.purge()?;
         }
 
         let res: Result<_, Error> = try {
             let mut files = Vec::new();
+            let mut findings = Vec::new();
 
             // Find all the files within `data.tar.gz` and write their path in `tmp_filesmap_path`.
             if manifest.kind() == Kind::Effective {
@@ -433,10 +877,15 @@ impl NPFCacheEntry {
                     format_err!("no data found even though the package is effective")
                 })?;
                 let mut data = Archive::new(GzDecoder::new(data_file.file()));
+                let mut budget = ArchiveBudget::new(&self.limits);
 
                 for entry in data.entries()? {
-                    let entry = entry?;
-                    let entry_path = entry.path()?;
+                    let mut entry = entry?;
+                    let entry_path = entry.path()?.into_owned();
+
+                    // Stop as soon as the archive is too big, before decompressing it any further
+                    budget.consume(&entry_path, entry.header().size()?)?;
+                    findings.extend(safety::classify(&entry)?);
 
                     // Quick beautifier without altering the meaning of the path
                     // before storing and printing it.
@@ -448,10 +897,19 @@ impl NPFCacheEntry {
                             _ => pretty_path.push(component),
                         }
                     }
-                    files.push(pretty_path.display().to_string());
+                    files.push(FileEntry::from_entry(pretty_path, &mut entry)?);
                 }
             }
 
+            // The safety policy is enforced on uploads: packages already published are kept, with their issues exposed
+            if !findings.is_empty() {
+                println!(
+                    "[CACHE] {} contains {} unsafe entries",
+                    npf_path.as_ref().display(),
+                    findings.len()
+                );
+            }
+
             // Copy all new files to their destination
             fs::create_dir_all(&self.cache_path)?;
 
@@ -459,7 +917,18 @@ impl NPFCacheEntry {
             io::copy(npf_explorer.open_manifest()?.file_mut(), &mut new_manifest)?;
 
             let filesmap = File::create(&self.filesmap_path)?;
-            serde_json::to_writer(filesmap, &files)?;
+            serde_json::to_writer(filesmap, &FilesMap::new(files))?;
+            fs::write(&self.filesmap_format_path, FILES_MAP_FORMAT.to_string())?;
+
+            let safety = File::create(&self.safety_path)?;
+            serde_json::to_writer(safety, &findings)?;
+
+            // Hash the NPF once, so that its digest doesn't have to be computed again for each request
+            let digest = NPFDigest {
+                sha256: HEXUPPER
+                    .encode(file_digest::<Sha256>(npf_path.as_ref())?.result().as_ref()),
+            };
+            serde_json::to_writer(File::create(&self.digest_path)?, &digest)?;
         };
 
         // Purge on error
@@ -479,54 +948,87 @@ impl NPFCacheEntry {
         Ok(toml::from_str(&content)?)
     }
 
+    /// The format `files.map` was written with, if known
+    fn filesmap_format(&self) -> Option<u32> {
+        fs::read_to_string(&self.filesmap_format_path)
+            .ok()
+            .and_then(|format| format.trim().parse().ok())
+    }
+
+    fn read_filesmap(&self) -> Result<FilesMap, Error> {
+        let file = File::open(&self.filesmap_path)?;
+        Ok(serde_json::from_reader(file)?)
+    }
+
     /// Return, if it exists, a list of all the content present in the package.
-    pub fn filesmap(&self) -> Result<Option<Vec<PathBuf>>, Error> {
+    pub fn filesmap(&self) -> Result<Option<Vec<FileEntry>>, Error> {
         if self.filesmap_path.exists() {
-            let file = File::open(&self.filesmap_path)?;
-            Ok(Some(serde_json::from_reader(file)?))
+            Ok(Some(self.read_filesmap()?.into_files()))
         } else {
             Ok(None)
         }
     }
 
-    /// Test if the package contains the given file, returning all the paths that matches the given query
-    pub fn contains(&self, query: &str, exact_match: bool) -> Result<Vec<PathBuf>, Error> {
-        let mut res = Vec::new();
+    /// Return the SHA-256 of the NPF, in uppercase hexadecimal.
+    pub fn sha256(&self) -> Result<String, Error> {
+        let file = File::open(&self.digest_path)?;
+        let digest: NPFDigest = serde_json::from_reader(file)?;
+        Ok(digest.sha256)
+    }
 
-        if let Some(filesmap) = self.filesmap()? {
-            let filesmap = filesmap
-                .iter()
-                .filter(|filesmap| filesmap.file_name().is_some())
-                .map(|filesmap| (filesmap.file_name().unwrap().to_str(), filesmap))
-                .filter(|(filename, _)| filename.is_some())
-                .map(|(filename, path)| (filename.unwrap(), path));
+    /// Return the safety issues found in the content of the package.
+    ///
+    /// Entries filled before the scanner existed are assumed to have no issue.
+    pub fn safety(&self) -> Result<Vec<SafetyFinding>, Error> {
+        if self.safety_path.exists() {
+            let file = File::open(&self.safety_path)?;
+            Ok(serde_json::from_reader(file)?)
+        } else {
+            Ok(Vec::new())
+        }
+    }
+}
 
-            if exact_match {
-                for (filename, path) in filesmap {
-                    if filename == query {
-                        res.push(path.clone());
-                    }
-                }
+/// Remove any temporary or staging file left by a previous instance of the server.
+pub fn clean_tmp_dirs(config: &Config) -> Result<(), Error> {
+    for dir in &[config.tmp_dir().to_path_buf(), config.staging_dir()] {
+        for entry in fs::read_dir(dir)? {
+            let path = entry?.path();
+
+            println!("[CLEAN] Removing stale file {}", path.display());
+            if path.is_dir() {
+                fs::remove_dir_all(&path)?;
             } else {
-                for (filename, path) in filesmap {
-                    if filename.contains(query) {
-                        res.push(path.clone());
-                    }
-                }
+                fs::remove_file(&path)?;
             }
         }
+    }
+    Ok(())
+}
 
-        Ok(res)
+/// Compute the digest of a file with the given algorithm, without holding it in memory
+pub fn file_digest<D: Digest>(path: &Path) -> Result<D, Error> {
+    let mut file = File::open(path)?;
+    let mut digest = D::new();
+    let mut buffer = [0u8; 8192];
+
+    loop {
+        let len = file.read(&mut buffer)?;
+        if len == 0 {
+            break;
+        }
+        digest.input(&buffer[..len]);
     }
+    Ok(digest)
 }
 
-/// Generate a valid path with a random component
-pub fn gen_tmp_filename() -> PathBuf {
+/// Generate a valid path with a random component within the given directory
+pub fn gen_tmp_filename(dir: &Path) -> PathBuf {
     let mut rng = thread_rng();
     let name: String = iter::repeat(())
         .map(|()| rng.sample(Alphanumeric))
         .take(10)
         .collect();
 
-    Path::new("/var/tmp/nest-server").join(&format!("nest_{}", name))
+    dir.join(&format!("nest_{}", name))
 }
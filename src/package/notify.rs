@@ -6,7 +6,7 @@ use std::time::Duration;
 use failure::Error;
 use notify::{watcher, DebouncedEvent, RecursiveMode, Watcher};
 
-use super::NPFManager;
+use super::{refresh_snapshot, NPFManager};
 use crate::config::Config;
 
 pub fn async_watch_fs(config: Arc<Config>, npf_manager: Arc<RwLock<NPFManager>>) {
@@ -77,6 +77,9 @@ pub fn async_watch_fs(config: Arc<Config>, npf_manager: Arc<RwLock<NPFManager>>)
             if let Err(e) = res {
                 eprintln!("[NOTIFY] Cache update failed: {:?}", e);
             }
+
+            // Done once the manager isn't locked anymore, as it takes a while
+            refresh_snapshot(&npf_manager);
         }
     });
 }
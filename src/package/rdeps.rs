@@ -0,0 +1,119 @@
+use std::collections::HashMap;
+
+use libnest::package::{
+    CategoryName, PackageID, PackageName, PackageRequirement, PackageShortName, RepositoryName,
+};
+use semver::Version;
+use serde::{Deserialize, Serialize};
+
+/// A version of a package depending on another package, with the requirement it uses
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct ReverseDependency {
+    category: CategoryName,
+    name: PackageName,
+    version: Version,
+    requirement: PackageRequirement,
+}
+
+impl ReverseDependency {
+    /// The ID of the dependent package, in the given repository
+    pub fn id(&self, repository: &RepositoryName) -> PackageID {
+        PackageID::from(
+            repository.clone(),
+            self.category.clone(),
+            self.name.clone(),
+            self.version.clone(),
+        )
+    }
+
+    pub fn requirement(&self) -> &PackageRequirement {
+        &self.requirement
+    }
+
+    /// Test if the requirement is satisfied by the given version of the required package
+    pub fn matches(&self, version: &Version) -> bool {
+        self.requirement.version_requirement().matches(version)
+    }
+}
+
+/// An index of the reverse dependencies of all packages of the repository.
+///
+/// Only requirements targeting this repository (or no repository in particular) are indexed.
+#[derive(Clone, Debug, Default)]
+pub struct ReverseDependencies {
+    required_by: HashMap<PackageShortName, HashMap<PackageID, ReverseDependency>>,
+    requires: HashMap<PackageID, Vec<PackageShortName>>,
+}
+
+impl ReverseDependencies {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn clear(&mut self) {
+        self.required_by.clear();
+        self.requires.clear();
+    }
+
+    /// Index the dependencies of the given version of a package, replacing those it had before
+    pub fn insert<'a, I>(&mut self, repository: &RepositoryName, id: &PackageID, dependencies: I)
+    where
+        I: IntoIterator<Item = &'a PackageRequirement>,
+    {
+        self.remove(id);
+
+        let mut requires = Vec::new();
+        for requirement in dependencies {
+            if requirement
+                .repository()
+                .as_ref()
+                .map_or(false, |r| r != repository)
+            {
+                continue;
+            }
+
+            let required =
+                PackageShortName::from(requirement.category().clone(), requirement.name().clone());
+            self.required_by
+                .entry(required.clone())
+                .or_default()
+                .insert(
+                    id.clone(),
+                    ReverseDependency {
+                        category: id.category().clone(),
+                        name: id.name().clone(),
+                        version: id.version().clone(),
+                        requirement: requirement.clone(),
+                    },
+                );
+            requires.push(required);
+        }
+        self.requires.insert(id.clone(), requires);
+    }
+
+    /// Remove the dependencies of the given version of a package from the index
+    pub fn remove(&mut self, id: &PackageID) {
+        for required in self.requires.remove(id).unwrap_or_default() {
+            if let Some(dependents) = self.required_by.get_mut(&required) {
+                dependents.remove(id);
+                if dependents.is_empty() {
+                    self.required_by.remove(&required);
+                }
+            }
+        }
+    }
+
+    /// Return all the versions of packages depending on the given package, sorted by category, name and version
+    pub fn of(&self, name: &PackageShortName) -> Vec<&ReverseDependency> {
+        let mut rdeps = self
+            .required_by
+            .get(name)
+            .map(|dependents| dependents.values().collect::<Vec<_>>())
+            .unwrap_or_default();
+
+        rdeps.sort_by(|a, b| {
+            (&a.category, &a.name, &a.version).cmp(&(&b.category, &b.name, &b.version))
+        });
+        rdeps
+    }
+}
@@ -0,0 +1,363 @@
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+use failure::Fail;
+use libnest::package::{
+    PackageID, PackageManifest, PackageRequirement, PackageShortName, RepositoryName,
+};
+use semver::Version;
+use serde::Serialize;
+
+/// The maximum number of candidate versions tried before a resolution is given up
+static MAX_STEPS: usize = 10_000;
+
+/// The maximum time spent on a resolution before it is given up
+static MAX_DURATION: Duration = Duration::from_secs(2);
+
+#[derive(Clone, Eq, PartialEq, Hash, Debug, Fail)]
+pub enum ResolutionError {
+    /// The requirements can't be satisfied, with a human-readable explanation
+    #[fail(display = "{}", _0)]
+    Conflict(String),
+    #[fail(display = "the resolution was given up after trying {} candidates", _0)]
+    TooComplex(usize),
+}
+
+/// A consistent set of packages satisfying a list of requirements
+#[derive(Serialize, Clone, Debug)]
+pub struct Resolution {
+    /// The packages to install, in the order they were selected
+    packages: Vec<PackageID>,
+    /// The requirements targeting other repositories, left for the client to resolve
+    external: Vec<PackageRequirement>,
+}
+
+/// A version of a package selected during the resolution
+#[derive(Clone, Debug)]
+struct Selected {
+    id: PackageID,
+    short_name: PackageShortName,
+    slot: String,
+}
+
+/// A requirement left to satisfy, with the package that introduced it (`None` for those given by the client)
+type Pending = (PackageRequirement, Option<PackageID>);
+
+/// The size of the state of the resolution when a choice was made, to undo everything that followed it
+#[derive(Copy, Clone, Debug)]
+struct Mark {
+    selected: usize,
+    pending: usize,
+    next_pending: usize,
+    external: usize,
+}
+
+/// A requirement that several versions can satisfy, revisited when the selected one leads to a conflict
+#[derive(Clone, Debug)]
+struct Choice {
+    short_name: PackageShortName,
+    reason: String,
+    candidates: Vec<Version>,
+    next_candidate: usize,
+    mark: Mark,
+    /// The conflict of the most recent candidate, as it's usually the most relevant one
+    conflict: Option<ResolutionError>,
+}
+
+/// A backtracking resolver.
+///
+/// The state is only ever appended to, so backtracking to a choice is done by truncating it back to the size it had
+/// when the choice was made, instead of copying it for each candidate.
+struct Resolver<'a, F> {
+    repository: &'a RepositoryName,
+    fetch: F,
+    manifests: HashMap<PackageShortName, Option<PackageManifest>>,
+    selected: Vec<Selected>,
+    /// The indexes in `selected` of the versions selected for each package
+    selected_by_name: HashMap<PackageShortName, Vec<usize>>,
+    pending: Vec<Pending>,
+    next_pending: usize,
+    external: Vec<PackageRequirement>,
+    choices: Vec<Choice>,
+    steps: usize,
+    started: Instant,
+}
+
+impl<'a, F> Resolver<'a, F>
+where
+    F: FnMut(&PackageShortName) -> Option<PackageManifest>,
+{
+    /// Satisfy the pending requirements, trying the most recent versions first and backtracking on conflicts
+    fn resolve(mut self) -> Result<Resolution, ResolutionError> {
+        while self.next_pending < self.pending.len() {
+            let (requirement, required_by) = self.pending[self.next_pending].clone();
+            self.next_pending += 1;
+
+            if requirement
+                .repository()
+                .as_ref()
+                .map_or(false, |r| r != self.repository)
+            {
+                if !self.external.contains(&requirement) {
+                    self.external.push(requirement);
+                }
+                continue;
+            }
+
+            let short_name =
+                PackageShortName::from(requirement.category().clone(), requirement.name().clone());
+            if self.is_satisfied(&short_name, &requirement) {
+                continue;
+            }
+
+            let reason = match &required_by {
+                Some(id) => format!("required by {}", id),
+                None => "requested".to_string(),
+            };
+
+            let r = match self.candidates(&short_name, &requirement, &reason) {
+                Ok(candidates) => {
+                    let mark = self.mark();
+                    self.choices.push(Choice {
+                        short_name,
+                        reason,
+                        candidates,
+                        next_candidate: 0,
+                        mark,
+                        conflict: None,
+                    });
+                    self.select_next_candidate()
+                }
+                Err(e) => Err(e),
+            };
+            if let Err(e) = r {
+                self.backtrack(e)?;
+            }
+        }
+
+        Ok(Resolution {
+            packages: self.selected.into_iter().map(|s| s.id).collect(),
+            external: self.external,
+        })
+    }
+
+    /// Find the manifest of the given package, fetching it only once per resolution
+    fn manifest(&mut self, short_name: &PackageShortName) -> Option<&PackageManifest> {
+        let fetch = &mut self.fetch;
+        self.manifests
+            .entry(short_name.clone())
+            .or_insert_with(|| fetch(short_name))
+            .as_ref()
+    }
+
+    /// Test if one of the versions selected for the given package matches the requirement
+    fn is_satisfied(
+        &self,
+        short_name: &PackageShortName,
+        requirement: &PackageRequirement,
+    ) -> bool {
+        self.selected_by_name
+            .get(short_name)
+            .map_or(false, |indexes| {
+                indexes.iter().any(|i| {
+                    requirement
+                        .version_requirement()
+                        .matches(self.selected[*i].id.version())
+                })
+            })
+    }
+
+    /// The versions of the given package matching the requirement, most recent first
+    fn candidates(
+        &mut self,
+        short_name: &PackageShortName,
+        requirement: &PackageRequirement,
+        reason: &str,
+    ) -> Result<Vec<Version>, ResolutionError> {
+        let manifest = self.manifest(short_name).ok_or_else(|| {
+            ResolutionError::Conflict(format!(
+                "{} ({}) doesn't exist in this repository",
+                requirement, reason
+            ))
+        })?;
+
+        let mut candidates = manifest
+            .versions()
+            .keys()
+            .filter(|version| requirement.version_requirement().matches(version))
+            .cloned()
+            .collect::<Vec<_>>();
+        candidates.sort_by(|a, b| b.cmp(a));
+
+        if candidates.is_empty() {
+            let available = manifest
+                .versions()
+                .keys()
+                .map(Version::to_string)
+                .collect::<Vec<_>>();
+            Err(ResolutionError::Conflict(format!(
+                "no version of {}/{} matches {} ({}), available versions are: {}",
+                manifest.category(),
+                manifest.name(),
+                requirement,
+                reason,
+                available.join(", ")
+            )))?;
+        }
+        Ok(candidates)
+    }
+
+    fn mark(&self) -> Mark {
+        Mark {
+            selected: self.selected.len(),
+            pending: self.pending.len(),
+            next_pending: self.next_pending,
+            external: self.external.len(),
+        }
+    }
+
+    /// Undo everything that happened since the given mark
+    fn undo(&mut self, mark: Mark) {
+        let selected_by_name = &mut self.selected_by_name;
+        for selected in self.selected.drain(mark.selected..) {
+            if let Some(indexes) = selected_by_name.get_mut(&selected.short_name) {
+                indexes.pop();
+            }
+        }
+        self.pending.truncate(mark.pending);
+        self.next_pending = mark.next_pending;
+        self.external.truncate(mark.external);
+    }
+
+    /// Give up if the resolution takes too long
+    fn step(&mut self) -> Result<(), ResolutionError> {
+        if self.steps >= MAX_STEPS || self.started.elapsed() > MAX_DURATION {
+            Err(ResolutionError::TooComplex(self.steps))
+        } else {
+            self.steps += 1;
+            Ok(())
+        }
+    }
+
+    /// Select the next candidate of the innermost choice, undoing the selection of the previous one.
+    ///
+    /// Once all the candidates were tried, the choice is dropped and its conflict is returned.
+    fn select_next_candidate(&mut self) -> Result<(), ResolutionError> {
+        loop {
+            let choice = self.choices.last_mut().expect("no choice to revisit");
+            let version = match choice.candidates.get(choice.next_candidate) {
+                Some(version) => version.clone(),
+                None => {
+                    let choice = self.choices.pop().expect("no choice to revisit");
+                    return Err(choice.conflict.expect("no candidate was tried"));
+                }
+            };
+            choice.next_candidate += 1;
+
+            let short_name = choice.short_name.clone();
+            let reason = choice.reason.clone();
+            let mark = choice.mark;
+
+            self.undo(mark);
+            self.step()?;
+
+            let manifest = self.manifests[&short_name]
+                .as_ref()
+                .expect("the candidates were found in the manifest");
+            let version_data = &manifest.versions()[&version];
+            let id = PackageID::from(
+                self.repository.clone(),
+                manifest.category().clone(),
+                manifest.name().clone(),
+                version,
+            );
+            let slot = version_data.slot().clone();
+            let dependencies = version_data
+                .dependencies()
+                .iter()
+                .map(|dependency| (dependency.clone(), Some(id.clone())))
+                .collect::<Vec<_>>();
+
+            let taken = self.selected_by_name.get(&short_name).and_then(|indexes| {
+                indexes
+                    .iter()
+                    .map(|i| &self.selected[*i])
+                    .find(|selected| selected.slot == slot)
+            });
+            if let Some(taken) = taken {
+                let conflict = ResolutionError::Conflict(format!(
+                    "{} ({}) can't be installed alongside {}, as they share the slot \"{}\"",
+                    id, reason, taken.id, taken.slot
+                ));
+                if let Some(choice) = self.choices.last_mut() {
+                    choice.conflict.get_or_insert(conflict);
+                }
+                continue;
+            }
+
+            self.selected_by_name
+                .entry(short_name.clone())
+                .or_default()
+                .push(self.selected.len());
+            self.selected.push(Selected {
+                id,
+                short_name,
+                slot,
+            });
+            self.pending.extend(dependencies);
+            return Ok(());
+        }
+    }
+
+    /// Record a conflict in the innermost choice, and revisit the choices until one of them can be changed
+    fn backtrack(&mut self, mut conflict: ResolutionError) -> Result<(), ResolutionError> {
+        loop {
+            if let ResolutionError::TooComplex(_) = conflict {
+                return Err(conflict);
+            }
+            match self.choices.last_mut() {
+                Some(choice) => {
+                    choice.conflict.get_or_insert(conflict);
+                }
+                None => return Err(conflict),
+            }
+            match self.select_next_candidate() {
+                Ok(()) => return Ok(()),
+                Err(e) => conflict = e,
+            }
+        }
+    }
+}
+
+/// Find a set of packages of the given repository satisfying all the given requirements and their dependencies.
+///
+/// Manifests are requested to `fetch` as they are needed, at most once per package, so that the caller doesn't have to
+/// lock the whole index during the resolution.
+/// At most one version of a package can be selected per slot.
+pub fn resolve<F>(
+    repository: &RepositoryName,
+    fetch: F,
+    requirements: &[PackageRequirement],
+) -> Result<Resolution, ResolutionError>
+where
+    F: FnMut(&PackageShortName) -> Option<PackageManifest>,
+{
+    let resolver = Resolver {
+        repository,
+        fetch,
+        manifests: HashMap::new(),
+        selected: Vec::new(),
+        selected_by_name: HashMap::new(),
+        pending: requirements
+            .iter()
+            .map(|requirement| (requirement.clone(), None))
+            .collect(),
+        next_pending: 0,
+        external: Vec::new(),
+        choices: Vec::new(),
+        steps: 0,
+        started: Instant::now(),
+    };
+
+    resolver.resolve()
+}
@@ -0,0 +1,161 @@
+use std::io::Read;
+use std::path::{Component, Path};
+
+use failure::Error;
+use flate2::read::GzDecoder;
+use libnest::package::NPFExplorer;
+use serde::{Deserialize, Serialize};
+use tar::{Archive, Entry, EntryType};
+
+use crate::config::Limits;
+use crate::package::limits::ArchiveBudget;
+
+/// The kinds of unsafe entries that can be found in `data.tar.gz`
+#[derive(Serialize, Deserialize, Copy, Clone, Eq, PartialEq, Hash, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum SafetyIssue {
+    /// The path contains a `..` component
+    PathTraversal,
+    /// The path is absolute
+    AbsolutePath,
+    /// The entry is a character or block device
+    DeviceFile,
+    /// The entry has the setuid or setgid bit set
+    Setuid,
+    /// The entry is writable by anyone
+    WorldWritable,
+    /// The entry is a link whose target is outside of the root of the package
+    EscapingLink,
+}
+
+/// An unsafe entry found in `data.tar.gz`
+#[derive(Serialize, Deserialize, Clone, Eq, PartialEq, Hash, Debug)]
+pub struct SafetyFinding {
+    path: String,
+    issue: SafetyIssue,
+    message: String,
+}
+
+impl SafetyFinding {
+    pub fn new<S: Into<String>>(path: &Path, issue: SafetyIssue, message: S) -> Self {
+        Self {
+            path: path.display().to_string(),
+            issue,
+            message: message.into(),
+        }
+    }
+}
+
+/// Test if following the given path from the root of the package leads out of it
+fn escapes_root(path: &Path) -> bool {
+    let mut depth = 0isize;
+
+    for component in path.components() {
+        match component {
+            Component::ParentDir => depth -= 1,
+            Component::Normal(_) => depth += 1,
+            Component::RootDir | Component::Prefix(_) => depth = 0,
+            Component::CurDir => (),
+        }
+        if depth < 0 {
+            return true;
+        }
+    }
+    false
+}
+
+/// Classify an entry of `data.tar.gz`, returning all the safety issues it has.
+pub fn classify<R: Read>(entry: &Entry<R>) -> Result<Vec<SafetyFinding>, Error> {
+    let path = entry.path()?;
+    let header = entry.header();
+    let entry_type = header.entry_type();
+    let mode = header.mode()?;
+    let mut findings = Vec::new();
+
+    if path.components().any(|c| c == Component::ParentDir) {
+        findings.push(SafetyFinding::new(
+            &path,
+            SafetyIssue::PathTraversal,
+            "the path contains a `..` component",
+        ));
+    }
+    if path.has_root() {
+        findings.push(SafetyFinding::new(
+            &path,
+            SafetyIssue::AbsolutePath,
+            "the path is absolute",
+        ));
+    }
+
+    match entry_type {
+        EntryType::Char | EntryType::Block => findings.push(SafetyFinding::new(
+            &path,
+            SafetyIssue::DeviceFile,
+            "the entry is a device file",
+        )),
+        EntryType::Symlink | EntryType::Link => {
+            if let Some(target) = entry.link_name()? {
+                // Symbolic links are relative to the directory containing them, hard links to the root of the archive
+                let resolved = match (entry_type, path.parent()) {
+                    (EntryType::Symlink, Some(parent)) if target.is_relative() => {
+                        parent.join(&target)
+                    }
+                    _ => target.to_path_buf(),
+                };
+
+                if escapes_root(&resolved) {
+                    findings.push(SafetyFinding::new(
+                        &path,
+                        SafetyIssue::EscapingLink,
+                        format!(
+                            "the link points to \"{}\", outside of the package",
+                            target.display()
+                        ),
+                    ));
+                }
+            }
+        }
+        _ => (),
+    }
+
+    if mode & 0o6000 != 0 {
+        findings.push(SafetyFinding::new(
+            &path,
+            SafetyIssue::Setuid,
+            format!("the entry has the setuid or setgid bit set ({:o})", mode),
+        ));
+    }
+
+    // Permissions of symbolic links are meaningless, and sticky directories (like `/tmp`) are fine
+    let is_sticky_dir = entry_type == EntryType::Directory && mode & 0o1000 != 0;
+    if mode & 0o002 != 0 && entry_type != EntryType::Symlink && !is_sticky_dir {
+        findings.push(SafetyFinding::new(
+            &path,
+            SafetyIssue::WorldWritable,
+            format!("the entry is writable by anyone ({:o})", mode),
+        ));
+    }
+
+    Ok(findings)
+}
+
+/// Walk through the entries of `data.tar.gz`, if any, returning all the safety issues found.
+///
+/// An empty list means the package is safe.
+/// Entries are also accounted for against the [`Limits`], failing with a `LimitExceededError`
+/// as soon as one of them is exceeded.
+pub fn scan(npf_explorer: &NPFExplorer, limits: &Limits) -> Result<Vec<SafetyFinding>, Error> {
+    let mut findings = Vec::new();
+
+    if let Some(data_file) = npf_explorer.open_data()? {
+        let mut data = Archive::new(GzDecoder::new(data_file.file()));
+        let mut budget = ArchiveBudget::new(limits);
+
+        for entry in data.entries()? {
+            let entry = entry?;
+            budget.consume(&entry.path()?, entry.header().size()?)?;
+            findings.extend(classify(&entry)?);
+        }
+    }
+    Ok(findings)
+}
@@ -0,0 +1,79 @@
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+
+use data_encoding::HEXLOWER;
+use ed25519_dalek::{PublicKey, Signature};
+use failure::Fail;
+use libnest::package::CategoryName;
+use serde::{Deserialize, Serialize};
+use sha2::Sha512;
+
+use crate::config::Config;
+
+/// Error returned when a signature doesn't match any of the keys allowed to sign a package
+#[derive(Clone, Eq, PartialEq, Hash, Debug, Fail)]
+#[fail(display = "{}", _0)]
+pub struct InvalidSignatureError(String);
+
+/// The maintainer whose key signed an NPF
+#[derive(Serialize, Deserialize, Clone, Eq, PartialEq, Hash, Debug)]
+pub struct Signer {
+    name: String,
+    public_key: String,
+}
+
+impl Signer {
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+}
+
+/// The path of the detached signature of an NPF, stored next to it as `<name>-<version>.nest.sig`
+pub fn signature_path(npf_path: &Path) -> PathBuf {
+    let mut path = OsString::from(npf_path.as_os_str());
+    path.push(".sig");
+    PathBuf::from(path)
+}
+
+/// Verify the detached signature of an NPF against the keys of the maintainers allowed to sign it.
+///
+/// Signatures are made with Ed25519ph (RFC 8032), so that the NPF doesn't have to be held in memory:
+/// `prehashed` is the SHA-512 of its content. The signature is given in hexadecimal.
+pub fn verify(
+    config: &Config,
+    category: &CategoryName,
+    maintainer: &str,
+    prehashed: &Sha512,
+    signature: &str,
+) -> Result<Signer, InvalidSignatureError> {
+    let signature = HEXLOWER
+        .decode(signature.trim().to_lowercase().as_bytes())
+        .ok()
+        .and_then(|bytes| Signature::from_bytes(&bytes).ok())
+        .ok_or_else(|| InvalidSignatureError("the signature is malformed".to_string()))?;
+
+    config
+        .maintainer_keys()
+        .iter()
+        .filter(|key| key.can_sign(category, maintainer))
+        .find(|key| {
+            HEXLOWER
+                .decode(key.public_key().to_lowercase().as_bytes())
+                .ok()
+                .and_then(|bytes| PublicKey::from_bytes(&bytes).ok())
+                .map_or(false, |public_key| {
+                    public_key
+                        .verify_prehashed(prehashed.clone(), None, &signature)
+                        .is_ok()
+                })
+        })
+        .map(|key| Signer {
+            name: key.name().to_string(),
+            public_key: key.public_key().to_lowercase(),
+        })
+        .ok_or_else(|| {
+            InvalidSignatureError(
+                "the signature doesn't match any key allowed to sign this package".to_string(),
+            )
+        })
+}
@@ -0,0 +1,203 @@
+use std::collections::BTreeMap;
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::os::unix::fs::OpenOptionsExt;
+use std::path::Path;
+
+use chrono::{DateTime, Utc};
+use data_encoding::{HEXLOWER, HEXUPPER};
+use ed25519_dalek::{ExpandedSecretKey, PublicKey, SecretKey};
+use failure::{format_err, Error, Fail, ResultExt};
+use rand::{thread_rng, Rng};
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+
+use crate::config::Config;
+use crate::package::changes::IndexRevision;
+
+#[derive(Clone, Eq, PartialEq, Ord, PartialOrd, Hash, Debug, Fail)]
+#[fail(display = "{}: invalid signing key", _0)]
+pub struct InvalidSigningKeyError(String);
+
+/// An ed25519 key used to sign the index, loaded from the file given in the configuration
+pub struct SigningKey {
+    secret: ExpandedSecretKey,
+    public: PublicKey,
+    expires: Option<DateTime<Utc>>,
+}
+
+impl SigningKey {
+    /// The identifier of the key: the beginning of its public key, in hexadecimal
+    pub fn id(&self) -> String {
+        HEXLOWER.encode(&self.public.as_bytes()[..8])
+    }
+
+    /// Test if the key is still in use, i.e. it doesn't expire or its overlap period isn't over yet
+    pub fn is_active(&self) -> bool {
+        self.expires.map_or(true, |expires| expires > Utc::now())
+    }
+
+    pub fn sign(&self, message: &[u8]) -> String {
+        HEXLOWER.encode(&self.secret.sign(message, &self.public).to_bytes())
+    }
+}
+
+/// The public part of a signing key, as published by the server
+#[derive(Serialize, Clone, Eq, PartialEq, Hash, Debug)]
+pub struct PublicKeyInfo {
+    key_id: String,
+    public_key: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    expires: Option<DateTime<Utc>>,
+}
+
+/// All the keys used to sign the index.
+///
+/// During a rotation, the old key is given an expiration date: until then, the index is signed with both keys
+/// and both are published, so that clients have time to learn about the new one.
+pub struct KeyRing {
+    keys: Vec<SigningKey>,
+}
+
+impl KeyRing {
+    pub fn load(config: &Config) -> Result<Self, Error> {
+        let keys = config
+            .signing_keys()
+            .iter()
+            .map(|key| -> Result<SigningKey, Error> {
+                let path = key.secret_key();
+                let seed = fs::read_to_string(path).context(path.display().to_string())?;
+                let seed = HEXLOWER
+                    .decode(seed.trim().to_lowercase().as_bytes())
+                    .map_err(|_| InvalidSigningKeyError(path.display().to_string()))?;
+                let secret = SecretKey::from_bytes(&seed)
+                    .map_err(|_| InvalidSigningKeyError(path.display().to_string()))?;
+
+                Ok(SigningKey {
+                    public: PublicKey::from(&secret),
+                    secret: ExpandedSecretKey::from(&secret),
+                    expires: key.expires().cloned(),
+                })
+            })
+            .collect::<Result<Vec<_>, Error>>()?;
+
+        Ok(Self { keys })
+    }
+
+    /// The keys currently in use
+    pub fn active_keys(&self) -> impl Iterator<Item = &SigningKey> {
+        self.keys.iter().filter(|key| key.is_active())
+    }
+
+    /// The identifiers of the keys currently in use, in the order they sign the index
+    pub fn active_key_ids(&self) -> Vec<String> {
+        self.active_keys().map(SigningKey::id).collect()
+    }
+
+    /// The public part of the keys currently in use
+    pub fn public_keys(&self) -> Vec<PublicKeyInfo> {
+        self.active_keys()
+            .map(|key| PublicKeyInfo {
+                key_id: key.id(),
+                public_key: HEXLOWER.encode(key.public.as_bytes()),
+                expires: key.expires,
+            })
+            .collect()
+    }
+}
+
+/// What is signed: the revision of the index, the digest of its snapshot and the digest of each NPF
+#[derive(Serialize, Clone, Eq, PartialEq, Hash, Debug)]
+struct IndexPayload<'a> {
+    repository: &'a str,
+    revision: IndexRevision,
+    date: DateTime<Utc>,
+    index_sha256: String,
+    packages: &'a BTreeMap<String, String>,
+}
+
+/// A signature of the payload by one of the keys
+#[derive(Serialize, Clone, Eq, PartialEq, Hash, Debug)]
+pub struct IndexSignature {
+    key_id: String,
+    signature: String,
+}
+
+/// The signed description of a revision of the index.
+///
+/// The payload is kept as a string, so that signatures can be checked against its exact bytes.
+#[derive(Serialize, Clone, Eq, PartialEq, Hash, Debug)]
+pub struct SignedIndex {
+    payload: String,
+    signatures: Vec<IndexSignature>,
+}
+
+impl SignedIndex {
+    /// Sign the given snapshot of the index with all the active keys.
+    ///
+    /// `packages` maps the ID of each package to the SHA-256 of its NPF.
+    /// Returns `None` if there is no active key.
+    pub fn sign(
+        key_ring: &KeyRing,
+        repository: &str,
+        revision: IndexRevision,
+        index: &[u8],
+        packages: &BTreeMap<String, String>,
+    ) -> Result<Option<Self>, Error> {
+        if key_ring.active_keys().next().is_none() {
+            return Ok(None);
+        }
+
+        let payload = serde_json::to_string(&IndexPayload {
+            repository,
+            revision,
+            date: Utc::now(),
+            index_sha256: HEXUPPER.encode(Sha256::digest(index).as_ref()),
+            packages,
+        })?;
+
+        let signatures = key_ring
+            .active_keys()
+            .map(|key| IndexSignature {
+                key_id: key.id(),
+                signature: key.sign(payload.as_bytes()),
+            })
+            .collect();
+
+        Ok(Some(Self {
+            payload,
+            signatures,
+        }))
+    }
+
+    /// The identifiers of the keys that signed the index
+    pub fn key_ids(&self) -> Vec<String> {
+        self.signatures
+            .iter()
+            .map(|signature| signature.key_id.clone())
+            .collect()
+    }
+}
+
+/// Implementation of `nest-server gen-signing-key <path>`.
+///
+/// Writes a new secret key to the given file, which must not exist yet, and returns its public key.
+pub fn gen_signing_key_command(args: &[String]) -> Result<String, Error> {
+    let path = match args {
+        [path] => Path::new(path),
+        _ => Err(format_err!("usage: nest-server gen-signing-key <path>"))?,
+    };
+
+    let seed: [u8; 32] = thread_rng().gen();
+    let secret = SecretKey::from_bytes(&seed).map_err(|e| format_err!("{}", e))?;
+
+    let mut file = OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .mode(0o600)
+        .open(path)
+        .context(path.display().to_string())?;
+    writeln!(file, "{}", HEXLOWER.encode(&seed))?;
+
+    Ok(HEXLOWER.encode(PublicKey::from(&secret).as_bytes()))
+}
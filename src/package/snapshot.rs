@@ -0,0 +1,146 @@
+use std::collections::BTreeMap;
+use std::io::Write;
+use std::sync::Arc;
+
+use failure::Error;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use libnest::package::PackageManifest;
+use serde_json::Value;
+
+use crate::package::changes::IndexRevision;
+use crate::package::signing::{KeyRing, SignedIndex};
+
+/// The compression level of the zstd variant
+static ZSTD_LEVEL: i32 = 15;
+
+/// The encodings in which the index snapshot is available
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug)]
+pub enum Encoding {
+    Identity,
+    Gzip,
+    Zstd,
+}
+
+impl Encoding {
+    /// The name of the encoding, as given in the `Accept-Encoding` header
+    pub fn name(self) -> &'static str {
+        match self {
+            Encoding::Identity => "identity",
+            Encoding::Gzip => "gzip",
+            Encoding::Zstd => "zstd",
+        }
+    }
+
+    /// The value of the `Content-Encoding` header for this encoding, if any
+    pub fn header_value(self) -> Option<&'static str> {
+        match self {
+            Encoding::Identity => None,
+            Encoding::Gzip => Some("gzip"),
+            Encoding::Zstd => Some("zstd"),
+        }
+    }
+}
+
+/// The content of a revision of the index, copied out of the [`NPFManager`](super::NPFManager) so that the snapshot
+/// can be compressed and signed without holding it.
+pub struct SnapshotSource {
+    revision: IndexRevision,
+    repository: String,
+    entries: Vec<Value>,
+    packages: BTreeMap<String, String>,
+    key_ring: Arc<KeyRing>,
+}
+
+impl SnapshotSource {
+    /// Collect the manifests, sorted by name and each serialized by the given function.
+    ///
+    /// `packages` maps the ID of each package to the SHA-256 of its NPF.
+    pub fn collect<'a, I, F>(
+        revision: IndexRevision,
+        repository: &str,
+        manifests: I,
+        entry: F,
+        packages: BTreeMap<String, String>,
+        key_ring: Arc<KeyRing>,
+    ) -> Self
+    where
+        I: Iterator<Item = &'a PackageManifest>,
+        F: Fn(&PackageManifest) -> Value,
+    {
+        let mut manifests = manifests.collect::<Vec<_>>();
+        manifests.sort_by(|a, b| (a.name(), a.category()).cmp(&(b.name(), b.category())));
+
+        Self {
+            revision,
+            repository: repository.to_string(),
+            entries: manifests.iter().map(|manifest| entry(manifest)).collect(),
+            packages,
+            key_ring,
+        }
+    }
+
+    pub fn revision(&self) -> IndexRevision {
+        self.revision
+    }
+
+    /// Serialize, compress and sign the index
+    pub fn build(self) -> Result<(IndexSnapshot, Option<SignedIndex>), Error> {
+        let plain = serde_json::to_vec(&self.entries)?;
+
+        let mut gzip = GzEncoder::new(Vec::new(), Compression::best());
+        gzip.write_all(&plain)?;
+        let gzip = gzip.finish()?;
+
+        let zstd = zstd::encode_all(plain.as_slice(), ZSTD_LEVEL)?;
+
+        let signed_index = SignedIndex::sign(
+            &self.key_ring,
+            &self.repository,
+            self.revision,
+            &plain,
+            &self.packages,
+        )?;
+
+        let snapshot = IndexSnapshot {
+            revision: self.revision,
+            count: self.entries.len(),
+            plain,
+            gzip,
+            zstd,
+        };
+        Ok((snapshot, signed_index))
+    }
+}
+
+/// The whole index, serialized once per change in all the supported encodings.
+///
+/// It holds the same content as an unpaginated `GET /api/pull`.
+#[derive(Clone, Eq, PartialEq, Hash, Debug)]
+pub struct IndexSnapshot {
+    revision: IndexRevision,
+    count: usize,
+    plain: Vec<u8>,
+    gzip: Vec<u8>,
+    zstd: Vec<u8>,
+}
+
+impl IndexSnapshot {
+    pub fn revision(&self) -> IndexRevision {
+        self.revision
+    }
+
+    /// The number of manifests in the index
+    pub fn count(&self) -> usize {
+        self.count
+    }
+
+    /// The serialized index, in the given encoding
+    pub fn encoded(&self, encoding: Encoding) -> &[u8] {
+        match encoding {
+            Encoding::Identity => &self.plain,
+            Encoding::Gzip => &self.gzip,
+            Encoding::Zstd => &self.zstd,
+        }
+    }
+}
@@ -0,0 +1,175 @@
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+
+use failure::{format_err, Error};
+use libnest::package::{Kind, NPFExplorer, PackageRequirement};
+use semver::Version;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tar::Archive;
+
+/// The maximum size of a manifest, as it is read in memory before the NPF is explored
+static MAX_MANIFEST_SIZE: u64 = 1024 * 1024;
+
+/// A problem found while validating an NPF
+#[derive(Serialize, Deserialize, Clone, Eq, PartialEq, Hash, Debug)]
+pub struct ValidationProblem {
+    field: String,
+    message: String,
+}
+
+impl ValidationProblem {
+    pub fn new<S: Into<String>, T: Into<String>>(field: S, message: T) -> Self {
+        Self {
+            field: field.into(),
+            message: message.into(),
+        }
+    }
+}
+
+/// Read the manifest of an NPF as is, without exploring the NPF
+fn read_raw_manifest(npf_path: &Path) -> Result<toml::Value, Error> {
+    let mut archive = Archive::new(File::open(npf_path)?);
+
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+        let path = entry.path()?.into_owned();
+
+        if path == Path::new("manifest.toml") || path == Path::new("./manifest.toml") {
+            if entry.header().size()? > MAX_MANIFEST_SIZE {
+                Err(format_err!("the manifest is too big"))?;
+            }
+
+            let mut raw_manifest = String::new();
+            entry.read_to_string(&mut raw_manifest)?;
+            return Ok(toml::from_str(&raw_manifest)?);
+        }
+    }
+    Err(format_err!("the NPF doesn't contain any manifest"))
+}
+
+/// Validate the manifest of an NPF on its raw form, before it is explored.
+///
+/// The parsed manifest is always canonical, and a manifest with a malformed dependency can't be parsed at all,
+/// so this ensures that:
+///   * The version is written in its canonical form
+///   * All dependencies are valid [`PackageRequirement`]s, and the package doesn't depend on itself
+///
+/// An empty list means the manifest is valid.
+pub fn validate_manifest(npf_path: &Path) -> Result<Vec<ValidationProblem>, Error> {
+    let raw_manifest = read_raw_manifest(npf_path)?;
+    let mut problems = Vec::new();
+
+    // Version
+    if let Some(raw_version) = raw_manifest.get("version").and_then(toml::Value::as_str) {
+        match Version::parse(raw_version) {
+            Ok(version) if version.to_string() != raw_version => {
+                problems.push(ValidationProblem::new(
+                    "version",
+                    format!(
+                        "\"{}\" isn't in its canonical form (\"{}\")",
+                        raw_version, version
+                    ),
+                ))
+            }
+            Err(e) => problems.push(ValidationProblem::new(
+                "version",
+                format!("\"{}\" isn't a valid version: {}", raw_version, e),
+            )),
+            _ => (),
+        }
+    }
+
+    // Dependencies, given as a table of `[repository::]category/name = "version requirement"`
+    let category = raw_manifest.get("category").and_then(toml::Value::as_str);
+    let name = raw_manifest.get("name").and_then(toml::Value::as_str);
+
+    match raw_manifest.get("dependencies") {
+        None => (),
+        Some(toml::Value::Table(dependencies)) => {
+            for (target, version_requirement) in dependencies {
+                let version_requirement = match version_requirement.as_str() {
+                    Some(version_requirement) => version_requirement,
+                    None => {
+                        problems.push(ValidationProblem::new(
+                            "dependencies",
+                            format!("\"{}\": the version requirement must be a string", target),
+                        ));
+                        continue;
+                    }
+                };
+
+                let raw_requirement = format!("{}#{}", target, version_requirement);
+                match serde_json::from_value::<PackageRequirement>(Value::String(
+                    raw_requirement.clone(),
+                )) {
+                    Err(e) => problems.push(ValidationProblem::new(
+                        "dependencies",
+                        format!(
+                            "\"{}\" isn't a valid package requirement: {}",
+                            raw_requirement, e
+                        ),
+                    )),
+                    Ok(requirement)
+                        if Some(requirement.category().as_ref()) == category
+                            && Some(requirement.name().as_ref()) == name =>
+                    {
+                        problems.push(ValidationProblem::new(
+                            "dependencies",
+                            format!("\"{}\": the package can't depend on itself", requirement),
+                        ))
+                    }
+                    Ok(_) => (),
+                }
+            }
+        }
+        Some(_) => problems.push(ValidationProblem::new(
+            "dependencies",
+            "the dependencies must be a table",
+        )),
+    }
+
+    Ok(problems)
+}
+
+/// Validate the content of an NPF before it is published.
+///
+/// This ensures that:
+///   * The kind of the package matches its content (only effective packages have a `data.tar.gz`)
+///   * None of the textual metadata fields are empty
+///
+/// The manifest itself is validated by [`validate_manifest`].
+/// An empty list means the NPF is valid.
+pub fn validate(npf_explorer: &NPFExplorer) -> Result<Vec<ValidationProblem>, Error> {
+    let manifest = npf_explorer.manifest();
+    let mut problems = Vec::new();
+
+    // Kind and content
+    let has_data = npf_explorer.open_data()?.is_some();
+    match manifest.kind() {
+        Kind::Effective if !has_data => problems.push(ValidationProblem::new(
+            "kind",
+            "effective packages must contain a `data.tar.gz`",
+        )),
+        Kind::Virtual if has_data => problems.push(ValidationProblem::new(
+            "kind",
+            "virtual packages must not contain a `data.tar.gz`",
+        )),
+        _ => (),
+    }
+
+    // Metadata
+    if let Value::Object(metadata) = serde_json::to_value(manifest.metadata())? {
+        for (field, value) in metadata {
+            if value.as_str().map_or(false, |s| s.trim().is_empty()) {
+                problems.push(ValidationProblem::new(
+                    format!("metadata.{}", field),
+                    "this field must not be empty",
+                ));
+            }
+        }
+    }
+
+    Ok(problems)
+}
@@ -1,6 +1,16 @@
+use chrono::{DateTime, Utc};
+use failure::format_err;
 use libnest::package::{CategoryName, PackageName};
+use rocket::http::RawStr;
+use rocket::request::FromFormValue;
 use semver::Version;
 
+use crate::api::graph::GraphFormat;
+use crate::api::page::SortKey;
+use crate::package::changes::IndexRevision;
+use crate::package::file_index::Position;
+use crate::package::graph::Direction;
+
 macro_rules! impl_into_value {
     ($Type:ident, $Value:ident) => {
         impl Into<$Value> for $Type {
@@ -68,3 +78,155 @@ impl VersionParam {
 
 impl_into_value!(VersionParam, Version);
 impl_from_param!(VersionParam, Version);
+
+impl<'v> FromFormValue<'v> for VersionParam {
+    type Error = ::failure::Error;
+
+    fn from_form_value(form_value: &'v RawStr) -> Result<VersionParam, Self::Error> {
+        let decoded_value = form_value.url_decode()?;
+
+        Ok(VersionParam {
+            value: Version::parse(&decoded_value)?,
+        })
+    }
+}
+
+/// A date in the RFC 3339 format, given as a query parameter.
+#[derive(Clone, Eq, PartialEq, Ord, PartialOrd, Hash, Debug)]
+pub struct DateTimeParam {
+    value: DateTime<Utc>,
+}
+
+impl DateTimeParam {
+    pub fn value(&self) -> &DateTime<Utc> {
+        &self.value
+    }
+}
+
+impl<'v> FromFormValue<'v> for DateTimeParam {
+    type Error = ::failure::Error;
+
+    fn from_form_value(form_value: &'v RawStr) -> Result<DateTimeParam, Self::Error> {
+        let decoded_value = form_value.url_decode()?;
+
+        Ok(DateTimeParam {
+            value: DateTime::parse_from_rfc3339(&decoded_value)?.with_timezone(&Utc),
+        })
+    }
+}
+
+/// Where a content search query must be found within the file name, given as a query parameter.
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug)]
+pub struct PositionParam {
+    value: Position,
+}
+
+impl_into_value!(PositionParam, Position);
+
+impl<'v> FromFormValue<'v> for PositionParam {
+    type Error = ::failure::Error;
+
+    fn from_form_value(form_value: &'v RawStr) -> Result<PositionParam, Self::Error> {
+        let value = match form_value.as_str() {
+            "exact" => Position::Exact,
+            "prefix" => Position::Prefix,
+            "suffix" => Position::Suffix,
+            "substring" => Position::Substring,
+            _ => Err(format_err!("{}: invalid position", form_value))?,
+        };
+
+        Ok(PositionParam { value })
+    }
+}
+
+/// The order of a list of results, given as a query parameter.
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug)]
+pub struct SortParam {
+    value: SortKey,
+}
+
+impl_into_value!(SortParam, SortKey);
+
+impl<'v> FromFormValue<'v> for SortParam {
+    type Error = ::failure::Error;
+
+    fn from_form_value(form_value: &'v RawStr) -> Result<SortParam, Self::Error> {
+        let value = match form_value.as_str() {
+            "name" => SortKey::Name,
+            "category" => SortKey::Category,
+            "date" => SortKey::Date,
+            "relevance" => SortKey::Relevance,
+            _ => Err(format_err!("{}: invalid sort key", form_value))?,
+        };
+
+        Ok(SortParam { value })
+    }
+}
+
+/// A revision of the index, given as a query parameter.
+///
+/// Parsing never fails: a malformed revision, like one from an older version of the server, is simply unknown.
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug)]
+pub struct RevisionParam {
+    value: Option<IndexRevision>,
+}
+
+impl Into<Option<IndexRevision>> for RevisionParam {
+    fn into(self) -> Option<IndexRevision> {
+        self.value
+    }
+}
+
+impl<'v> FromFormValue<'v> for RevisionParam {
+    type Error = ::failure::Error;
+
+    fn from_form_value(form_value: &'v RawStr) -> Result<RevisionParam, Self::Error> {
+        Ok(RevisionParam {
+            value: form_value.as_str().parse().ok(),
+        })
+    }
+}
+
+/// The format of a dependency graph, given as a query parameter.
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug)]
+pub struct GraphFormatParam {
+    value: GraphFormat,
+}
+
+impl_into_value!(GraphFormatParam, GraphFormat);
+
+impl<'v> FromFormValue<'v> for GraphFormatParam {
+    type Error = ::failure::Error;
+
+    fn from_form_value(form_value: &'v RawStr) -> Result<GraphFormatParam, Self::Error> {
+        let value = match form_value.as_str() {
+            "json" => GraphFormat::Json,
+            "dot" => GraphFormat::Dot,
+            _ => Err(format_err!("{}: invalid graph format", form_value))?,
+        };
+
+        Ok(GraphFormatParam { value })
+    }
+}
+
+/// The edges to follow when exploring a dependency graph, given as a query parameter.
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug)]
+pub struct DirectionParam {
+    value: Direction,
+}
+
+impl_into_value!(DirectionParam, Direction);
+
+impl<'v> FromFormValue<'v> for DirectionParam {
+    type Error = ::failure::Error;
+
+    fn from_form_value(form_value: &'v RawStr) -> Result<DirectionParam, Self::Error> {
+        let value = match form_value.as_str() {
+            "deps" => Direction::Dependencies,
+            "rdeps" => Direction::ReverseDependencies,
+            _ => Err(format_err!("{}: invalid direction", form_value))?,
+        };
+
+        Ok(DirectionParam { value })
+    }
+}